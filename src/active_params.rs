@@ -1,56 +1,396 @@
-use chrono::NaiveDate;
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use chrono::{DateTime, Local, NaiveDate};
 use serde::Deserialize;
 use serde::Serialize;
+use strum::Display;
 use tabled::Tabled;
+use uuid::Uuid;
 
+use crate::error::SigoError;
 use crate::utils;
 use crate::Priority;
 
 #[derive(Debug, Tabled, Serialize, Deserialize, Clone)]
+#[serde(from = "ActiveParamsRecord")]
 pub struct ActiveParams {
     pub id: u32,
+    #[tabled(skip)]
+    pub uuid: Uuid,
     #[tabled(rename = "P", display_with = "utils::display_option_priority")]
     pub priority: Option<Priority>,
-    #[tabled(display_with = "utils::display_vec_string")]
-    pub description: Vec<String>,
+    #[tabled(display_with("utils::display_description", self))]
+    pub description: String,
+    #[tabled(skip)]
+    pub annotations: Vec<Annotation>,
+    #[tabled(display_with = "utils::display_option_string")]
+    pub project: Option<String>,
+    #[tabled(display_with = "utils::display_tags")]
+    pub tags: BTreeSet<String>,
     #[tabled(display_with = "utils::display_option_date")]
     pub due: Option<NaiveDate>,
+    #[tabled(rename = "age", display_with = "utils::display_option_age")]
+    pub entry: Option<DateTime<Local>>,
+    #[tabled(skip)]
+    pub modified: Option<DateTime<Local>>,
+    #[tabled(skip)]
+    pub history: Vec<StateChange>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Display, Serialize, Deserialize)]
+pub enum State {
+    Ready,
+    Waiting,
+    Completed,
+}
+
+/// Changes requested by `sigo modify`, `None` leaves the field as it is.
+pub struct Modification {
+    pub priority: Option<Update<Priority>>,
+    pub due: Option<Update<NaiveDate>>,
+    pub description: Option<String>,
+    pub clear_annotations: bool,
+    pub tags: Vec<TagChange>,
+    pub project: Option<Update<String>>,
+}
+
+/// `+tag` adds the tag to a sigo, `-tag` removes it.
+#[derive(Clone, Debug)]
+pub enum TagChange {
+    Add(String),
+    Remove(String),
+}
+
+impl FromStr for TagChange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (sign, tag) = s.split_at(s.chars().next().map_or(0, char::len_utf8));
+        if tag.is_empty() || tag.contains(char::is_whitespace) {
+            return Err(format!(
+                "'{}' is not a tag, tags are like +review or -review.",
+                s
+            ));
+        }
+        match sign {
+            "+" => Ok(TagChange::Add(tag.to_string())),
+            "-" => Ok(TagChange::Remove(tag.to_string())),
+            _ => Err(format!(
+                "'{}' is not a tag, tags are like +review or -review.",
+                s
+            )),
+        }
+    }
+}
+
+/// New value of an optional field, `Clear` unsets it (`--due none`).
+#[derive(Copy, Clone, Debug)]
+pub enum Update<T> {
+    Set(T),
+    Clear,
+}
+
+impl<T> Update<T> {
+    pub fn parse<F>(s: &str, parse: F) -> Result<Self, String>
+    where
+        F: Fn(&str) -> Result<T, String>,
+    {
+        if s.eq_ignore_ascii_case("none") {
+            Ok(Update::Clear)
+        } else {
+            parse(s).map(Update::Set)
+        }
+    }
+
+    fn into_option(self) -> Option<T> {
+        match self {
+            Update::Set(value) => Some(value),
+            Update::Clear => None,
+        }
+    }
+}
+
+/// A note added to a sigo after its description.
+/// Annotations are numbered from 1 in the order they were added.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Annotation {
+    pub entry: Option<DateTime<Local>>,
+    pub text: String,
+}
+
+/// The state a sigo moved into and when.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct StateChange {
+    pub state: State,
+    pub at: DateTime<Local>,
+}
+
+// projects are dotted paths like infra.k8s.upgrade, each name is a parent of the next
+pub fn parse_project(s: &str) -> Result<String, String> {
+    if s.split('.')
+        .all(|name| !name.is_empty() && !name.contains(char::is_whitespace))
+    {
+        Ok(s.to_string())
+    } else {
+        Err(format!(
+            "'{}' is not a project, projects are dotted names like infra.k8s.upgrade.",
+            s
+        ))
+    }
+}
+
+impl TagChange {
+    pub fn apply(tags: &BTreeSet<String>, changes: &[TagChange]) -> BTreeSet<String> {
+        let mut tags = tags.clone();
+        for change in changes {
+            match change {
+                TagChange::Add(tag) => tags.insert(tag.clone()),
+                TagChange::Remove(tag) => tags.remove(tag),
+            };
+        }
+        tags
+    }
 }
 
 impl ActiveParams {
+    pub fn legacy_uuid(id: u32, description: &[String]) -> Uuid {
+        let name = format!("{}\n{}", id, description.join("\n"));
+        Uuid::new_v5(&Uuid::NAMESPACE_OID, name.as_bytes())
+    }
+
+    pub fn has_uuid_prefix(&self, prefix: &str) -> bool {
+        self.uuid.hyphenated().to_string().starts_with(prefix)
+            || self.uuid.simple().to_string().starts_with(prefix)
+    }
+
     pub fn get_primary_description(&self) -> String {
-        self.description
-            .first()
-            .unwrap_or(&"".to_owned())
-            .to_string()
+        self.description.clone()
     }
 
     pub fn annotate_description(&self, text: &str) -> Self {
-        let mut description = self.description.clone();
-        description.push(text.to_string());
+        let now = Local::now();
+        let mut annotations = self.annotations.clone();
+        annotations.push(Annotation {
+            entry: Some(now),
+            text: text.to_string(),
+        });
         Self {
-            id: self.id,
-            description,
-            priority: self.priority,
-            due: self.due,
+            annotations,
+            modified: Some(now),
+            ..self.clone()
+        }
+    }
+
+    pub fn edit_annotation(&self, number: usize, text: &str) -> Result<Self, SigoError> {
+        let mut annotations = self.annotations.clone();
+        let annotation = number
+            .checked_sub(1)
+            .and_then(|index| annotations.get_mut(index))
+            .ok_or(SigoError::AnnotationNotFound(self.id, number))?;
+        annotation.text = text.to_string();
+        Ok(Self {
+            annotations,
+            modified: Some(Local::now()),
+            ..self.clone()
+        })
+    }
+
+    pub fn remove_annotation(&self, number: usize) -> Result<Self, SigoError> {
+        if number == 0 || self.annotations.len() < number {
+            return Err(SigoError::AnnotationNotFound(self.id, number));
+        }
+        let mut annotations = self.annotations.clone();
+        annotations.remove(number - 1);
+        Ok(Self {
+            annotations,
+            modified: Some(Local::now()),
+            ..self.clone()
+        })
+    }
+
+    pub fn modify_priority(&self, priority: Option<Update<Priority>>) -> Self {
+        match priority.map(Update::into_option) {
+            Some(priority) if priority != self.priority => Self {
+                priority,
+                modified: Some(Local::now()),
+                ..self.clone()
+            },
+            _ => self.clone(),
         }
     }
 
-    pub fn modify_priority(&self, priority: Option<Priority>) -> Self {
+    pub fn modify_due(&self, due: Option<Update<NaiveDate>>) -> Self {
+        match due.map(Update::into_option) {
+            Some(due) if due != self.due => Self {
+                due,
+                modified: Some(Local::now()),
+                ..self.clone()
+            },
+            _ => self.clone(),
+        }
+    }
+
+    pub fn modify_tags(&self, changes: &[TagChange]) -> Self {
+        self.replace_tags(TagChange::apply(&self.tags, changes))
+    }
+
+    pub fn modify_project(&self, project: Option<Update<String>>) -> Self {
+        match project.map(Update::into_option) {
+            Some(project) if project != self.project => Self {
+                project,
+                modified: Some(Local::now()),
+                ..self.clone()
+            },
+            _ => self.clone(),
+        }
+    }
+
+    // a sigo in infra.k8s is also in infra
+    pub fn is_in_project(&self, project: &str) -> bool {
+        self.project.as_ref().is_some_and(|own| {
+            own == project
+                || own
+                    .strip_prefix(project)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+    }
+
+    pub fn replace_tags(&self, tags: BTreeSet<String>) -> Self {
+        if tags == self.tags {
+            return self.clone();
+        }
         Self {
-            id: self.id,
-            description: self.description.clone(),
-            priority: priority.or(self.priority),
-            due: self.due,
+            tags,
+            modified: Some(Local::now()),
+            ..self.clone()
         }
     }
 
-    pub fn modify_due(&self, due: Option<NaiveDate>) -> Self {
+    // annotations whose text is unchanged keep their timestamp
+    pub fn replace_annotations(&self, texts: Vec<String>) -> Self {
+        let now = Local::now();
+        let mut unused = self.annotations.clone();
+        let annotations = texts
+            .into_iter()
+            .map(|text| match unused.iter().position(|a| a.text == text) {
+                Some(index) => unused.remove(index),
+                None => Annotation {
+                    entry: Some(now),
+                    text,
+                },
+            })
+            .collect::<Vec<Annotation>>();
+        if annotations == self.annotations {
+            return self.clone();
+        }
         Self {
-            id: self.id,
-            description: self.description.clone(),
-            priority: self.priority,
-            due: due.or(self.due),
+            annotations,
+            modified: Some(now),
+            ..self.clone()
+        }
+    }
+
+    pub fn modify_description(
+        &self,
+        description: Option<&str>,
+        clear_annotations: bool,
+    ) -> Result<Self, SigoError> {
+        if description.is_none() && !clear_annotations {
+            return Ok(self.clone());
+        }
+        let description = description.unwrap_or(&self.description).trim();
+        if description.is_empty() {
+            return Err(SigoError::EmptyDescription(self.id));
+        }
+        let annotations = if clear_annotations {
+            Vec::new()
+        } else {
+            self.annotations.clone()
+        };
+        if description == self.description && annotations == self.annotations {
+            return Ok(self.clone());
+        }
+        Ok(Self {
+            description: description.to_string(),
+            annotations,
+            modified: Some(Local::now()),
+            ..self.clone()
+        })
+    }
+
+    pub fn change_state(&self, state: State) -> Self {
+        let now = Local::now();
+        let mut history = self.history.clone();
+        history.push(StateChange { state, at: now });
+        Self {
+            modified: Some(now),
+            history,
+            ..self.clone()
+        }
+    }
+}
+
+// sigos written before uuids and timestamps were stored have neither, derive a stable uuid
+// from what they have, and keep annotations as the 2nd and later lines of the description
+#[derive(Deserialize)]
+struct ActiveParamsRecord {
+    id: u32,
+    uuid: Option<Uuid>,
+    priority: Option<Priority>,
+    description: DescriptionRecord,
+    #[serde(default)]
+    annotations: Vec<Annotation>,
+    #[serde(default)]
+    tags: BTreeSet<String>,
+    #[serde(default)]
+    project: Option<String>,
+    due: Option<NaiveDate>,
+    #[serde(default)]
+    entry: Option<DateTime<Local>>,
+    #[serde(default)]
+    modified: Option<DateTime<Local>>,
+    #[serde(default)]
+    history: Vec<StateChange>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DescriptionRecord {
+    Text(String),
+    Lines(Vec<String>),
+}
+
+impl From<ActiveParamsRecord> for ActiveParams {
+    fn from(record: ActiveParamsRecord) -> Self {
+        let (description, annotations) = match record.description {
+            DescriptionRecord::Text(description) => (description, record.annotations),
+            DescriptionRecord::Lines(lines) => {
+                let mut lines = lines.into_iter();
+                let description = lines.next().unwrap_or_default();
+                let annotations = lines
+                    .map(|text| Annotation { entry: None, text })
+                    .collect::<Vec<Annotation>>();
+                (description, annotations)
+            }
+        };
+        let uuid = record.uuid.unwrap_or_else(|| {
+            let mut lines = vec![description.clone()];
+            lines.extend(annotations.iter().map(|a| a.text.clone()));
+            ActiveParams::legacy_uuid(record.id, &lines)
+        });
+        Self {
+            id: record.id,
+            uuid,
+            priority: record.priority,
+            description,
+            annotations,
+            tags: record.tags,
+            project: record.project,
+            due: record.due,
+            entry: record.entry,
+            modified: record.modified,
+            history: record.history,
         }
     }
 }
@@ -75,9 +415,45 @@ impl PartialOrd for ActiveParams {
 impl PartialEq for ActiveParams {
     fn eq(&self, other: &Self) -> bool {
         self.id == other.id
+            && self.uuid == other.uuid
             && self.description == other.description
+            && self.annotations == other.annotations
+            && self.tags == other.tags
+            && self.project == other.project
             && self.priority == other.priority
     }
 }
 
 impl Eq for ActiveParams {}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn description_lines_become_description_and_annotations() {
+        let params = serde_json::from_value::<ActiveParams>(json!({
+            "id": 1,
+            "priority": "H",
+            "description": ["a", "note"],
+            "due": null
+        }))
+        .unwrap();
+        assert_eq!(params.description, "a");
+        assert_eq!(
+            params.annotations,
+            vec![Annotation {
+                entry: None,
+                text: "note".to_string()
+            }]
+        );
+        assert_eq!(params.priority, Some(Priority::H));
+        assert_eq!(
+            params.uuid,
+            ActiveParams::legacy_uuid(1, &["a".to_string(), "note".to_string()])
+        );
+        assert_eq!(params.entry, None);
+    }
+}
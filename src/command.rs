@@ -1,133 +1,409 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::Local;
+
 use crate::{
+    active_params::{ActiveParams, Modification, State, TagChange},
     config::MyConfig,
-    display::SigoDisplay,
+    display::{ProjectCount, SigoDisplay, TagCount},
+    editor,
     error::*,
-    file::{add_task, read_tasks, ActiveFilable},
-    task::{ReadyTask, Task, WaitingTask},
+    file::{add_task, lock_store, read_tasks, recover, undo, ActiveFilable, Transaction},
+    filter::Filter,
+    task::{CompletedTask, ReadyTask, Selector, Task, TaskRef, WaitingTask},
+    urgency::{self, urgency, Urgent},
+    utils::confirm,
     AppArg, Command,
 };
 
-// TODO: DRY get id and match pattern
 pub fn run(cfg: &MyConfig, args: AppArg) -> Result<SigoDisplay> {
     match args.command {
+        Command::Edit { id } => edit_task(cfg, &id),
+        command => in_transaction(cfg, |tx| run_command(cfg, tx, command)),
+    }
+}
+
+fn in_transaction<T, F>(cfg: &MyConfig, f: F) -> Result<T>
+where
+    F: FnOnce(&mut Transaction) -> Result<T>,
+{
+    let _lock = lock_store(cfg)?;
+    recover(cfg)?;
+    let mut tx = Transaction::begin(cfg);
+    let output = f(&mut tx)?;
+    tx.commit()?;
+    Ok(output)
+}
+
+// the store is unlocked while the editor is open so other sigo commands keep working,
+// and the edit is only saved if nobody changed the sigo meanwhile
+fn edit_task(cfg: &MyConfig, task_ref: &TaskRef) -> Result<SigoDisplay> {
+    let task = in_transaction(cfg, |tx| Task::get(tx, task_ref))?;
+    if let Task::Completed(task) = task {
+        return Err(SigoError::TaskAlreadyCompleted(task.active_params.id));
+    }
+    let edited_params = editor::edit(task.active_params())?;
+    in_transaction(cfg, |tx| {
+        let uuid = TaskRef::Uuid(task.active_params().uuid.to_string());
+        let current = Task::get(tx, &uuid)?;
+        if current.state() != task.state()
+            || current.active_params().modified != task.active_params().modified
+        {
+            return Err(SigoError::EditConflict(task.active_params().id));
+        }
+        match current {
+            Task::Ready(task) => {
+                let task = task.update(tx, task.with_active_params(edited_params))?;
+                Ok(SigoDisplay::EditTask(task.active_params))
+            }
+            Task::Waiting(task) => {
+                let task = task.update(tx, task.with_active_params(edited_params))?;
+                Ok(SigoDisplay::EditTask(task.active_params))
+            }
+            Task::Completed(task) => Err(SigoError::TaskAlreadyCompleted(task.active_params.id)),
+        }
+    })
+}
+
+// TODO: DRY get id and match pattern
+fn run_command(cfg: &MyConfig, tx: &mut Transaction, command: Command) -> Result<SigoDisplay> {
+    let now = Local::now();
+    match command {
         Command::Add {
             description,
             priority,
             waiting,
             due,
+            project,
+            tags,
         } => {
-            let new_task =
-                add_task::<ReadyTask>(cfg, ReadyTask::new(cfg, &description, priority, due)?)?;
+            let tags = TagChange::apply(&BTreeSet::new(), &tags);
+            let new_task = ReadyTask::new(tx, &description, priority, due, tags, project)?;
+            let new_task = add_task::<ReadyTask>(tx, new_task)?;
             if waiting {
-                let new_task = new_task.wait(cfg, &None)?;
-                Ok(SigoDisplay::CreateWaitingTask(new_task.active_params.id))
+                let new_task = new_task.wait(tx, &None)?;
+                Ok(SigoDisplay::CreateWaitingTask(
+                    new_task.active_params.id,
+                    new_task.active_params.uuid,
+                ))
             } else {
-                Ok(SigoDisplay::CreateReadyTask(new_task.active_params.id))
+                Ok(SigoDisplay::CreateReadyTask(
+                    new_task.active_params.id,
+                    new_task.active_params.uuid,
+                ))
             }
         }
-        Command::Modify { id, priority, due } => {
-            let task = Task::get_by_id(cfg, id)?;
-            match task {
-                Task::Ready(task) => {
-                    task.modify(cfg, priority, due)?;
-                    Ok(SigoDisplay::ModifyTask(
-                        task.active_params.id,
-                        task.active_params.get_primary_description(),
-                    ))
-                }
-                Task::Waiting(task) => {
-                    task.modify(cfg, priority, due)?;
-                    Ok(SigoDisplay::ModifyTask(
-                        task.active_params.id,
-                        task.active_params.get_primary_description(),
-                    ))
-                }
-                Task::Completed(_) => panic!(),
+        Command::Modify {
+            id,
+            tags,
+            priority,
+            due,
+            text,
+            clear_annotations,
+            project,
+            yes,
+        } => {
+            if !yes && !confirm_filter(tx, &id, "Modify")? {
+                return keep_tasks(tx, &id);
             }
+            let modification = Modification {
+                priority,
+                due,
+                description: text,
+                clear_annotations,
+                tags,
+                project,
+            };
+            for_each_task(tx, &id, |tx, task| modify_task(tx, task, &modification))
         }
-        Command::Done { id } => {
-            let task = Task::get_by_id(cfg, id)?;
-            match task {
-                Task::Ready(task) => {
-                    task.complete(cfg)?;
-                    Ok(SigoDisplay::CompleteTask(
-                        id,
-                        task.active_params.get_primary_description(),
-                    ))
-                }
-                Task::Waiting(task) => {
-                    task.complete(cfg)?;
-                    Ok(SigoDisplay::CompleteTask(
-                        id,
-                        task.active_params.get_primary_description(),
-                    ))
-                }
-                Task::Completed(_) => panic!(),
+        Command::Edit { .. } => unreachable!("edit runs outside of a single transaction"),
+        Command::Done { id, yes } => {
+            if !yes && !confirm_filter(tx, &id, "Complete")? {
+                return keep_tasks(tx, &id);
             }
+            for_each_task(tx, &id, done_task)
         }
-        Command::Wait { id, text } => {
-            let task = Task::get_by_id(cfg, id)?;
-            match task {
-                Task::Ready(task) => {
-                    let task = task.wait(cfg, &text)?;
-                    Ok(SigoDisplay::WaitTask(
-                        task.active_params.id,
-                        task.active_params.get_primary_description(),
-                    ))
-                }
-                Task::Waiting(task) => Ok(SigoDisplay::WaitWaitingTask(
-                    task.active_params.id,
-                    task.active_params.get_primary_description(),
-                )),
-                Task::Completed(_) => panic!(),
-            }
+        Command::Delete { id, yes } => {
+            for_each_task(tx, &id, |tx, task| delete_task(tx, task, yes))
         }
-        Command::Back { id, text } => {
-            let task = Task::get_by_id(cfg, id)?;
-            match task {
-                Task::Ready(task) => Ok(SigoDisplay::BackReadyTask(
-                    task.active_params.id,
-                    task.active_params.get_primary_description(),
-                )),
-                Task::Waiting(task) => {
-                    let task = task.back(cfg, &text)?;
-                    Ok(SigoDisplay::BackTask(
-                        task.active_params.id,
-                        task.active_params.get_primary_description(),
-                    ))
-                }
-                Task::Completed(_) => panic!(),
+        Command::Wait { id, text, yes } => {
+            if !yes && !confirm_filter(tx, &id, "Change to waiting")? {
+                return keep_tasks(tx, &id);
             }
+            for_each_task(tx, &id, |tx, task| wait_task(tx, task, &text))
         }
-        Command::Annotate { id, text } => {
-            let task = Task::get_by_id(cfg, id)?;
+        Command::Back { id, text } => for_each_task(tx, &id, |tx, task| back_task(tx, task, &text)),
+        Command::Annotate { id, text, edit } => {
+            for_each_task(tx, &id, |tx, task| annotate_task(tx, task, &text, edit))
+        }
+        Command::Denotate { id, number } => {
+            let task = Task::get(tx, &id)?;
             match task {
                 Task::Ready(task) => {
-                    task.annotate(cfg, &text)?;
-                    Ok(SigoDisplay::AnnotateTask(
-                        task.active_params.id,
-                        task.active_params.get_primary_description(),
-                    ))
+                    let task = task.denotate(tx, number)?;
+                    Ok(SigoDisplay::DenotateTask(task.active_params))
                 }
                 Task::Waiting(task) => {
-                    task.annotate(cfg, &text)?;
-                    Ok(SigoDisplay::AnnotateTask(
-                        task.active_params.id,
-                        task.active_params.get_primary_description(),
-                    ))
+                    let task = task.denotate(tx, number)?;
+                    Ok(SigoDisplay::DenotateTask(task.active_params))
+                }
+                Task::Completed(task) => {
+                    Err(SigoError::TaskAlreadyCompleted(task.active_params.id))
                 }
-                Task::Completed(_) => panic!(),
             }
         }
-        Command::List => {
-            let mut tasks = read_tasks::<ReadyTask>(cfg)?;
-            tasks.sort();
+        Command::Reopen { id, waiting } => {
+            let task = CompletedTask::get(tx, &id)?.reopen(tx)?;
+            if waiting {
+                let task = task.wait(tx, &None)?;
+                Ok(SigoDisplay::ReopenWaitingTask(task.active_params))
+            } else {
+                Ok(SigoDisplay::ReopenTask(task.active_params))
+            }
+        }
+        Command::Info { id } => {
+            let task = Task::get(tx, &id)?;
+            Ok(SigoDisplay::TaskInfo(task))
+        }
+        Command::List { sort, filter } => {
+            let filter = Filter::from_args(&filter).map_err(SigoError::InvalidFilter)?;
+            let mut tasks = read_tasks::<ReadyTask>(tx)?
+                .into_iter()
+                .filter(|t| filter.matches(&t.active_params))
+                .map(|task| Urgent {
+                    urgency: urgency(&task.active_params, &cfg.urgency, now),
+                    task,
+                })
+                .collect::<Vec<Urgent<ReadyTask>>>();
+            urgency::sort(&mut tasks, &sort, |t| &t.active_params);
             Ok(SigoDisplay::ListReadyTasks(tasks))
         }
-        Command::Waiting => {
-            let mut tasks = read_tasks::<WaitingTask>(cfg)?;
-            tasks.sort();
+        Command::Waiting { sort, filter } => {
+            let filter = Filter::from_args(&filter).map_err(SigoError::InvalidFilter)?;
+            let mut tasks = read_tasks::<WaitingTask>(tx)?
+                .into_iter()
+                .filter(|t| filter.matches(&t.active_params))
+                .map(|task| Urgent {
+                    urgency: urgency(&task.active_params, &cfg.urgency, now),
+                    task,
+                })
+                .collect::<Vec<Urgent<WaitingTask>>>();
+            urgency::sort(&mut tasks, &sort, |t| &t.active_params);
             Ok(SigoDisplay::ListWaitingTasks(tasks))
         }
+        Command::Completed { since, until } => {
+            let mut tasks = read_tasks::<CompletedTask>(tx)?
+                .into_iter()
+                .filter(|t| t.is_completed_between(since, until))
+                .collect::<Vec<CompletedTask>>();
+            tasks.sort_by_key(|t| t.completed_at);
+            Ok(SigoDisplay::ListCompletedTasks(tasks))
+        }
+        Command::Tags => {
+            let mut counts = BTreeMap::<String, usize>::new();
+            let ready_tasks = read_tasks::<ReadyTask>(tx)?;
+            let waiting_tasks = read_tasks::<WaitingTask>(tx)?;
+            let all_params = ready_tasks
+                .iter()
+                .map(|t| &t.active_params)
+                .chain(waiting_tasks.iter().map(|t| &t.active_params));
+            for params in all_params {
+                for tag in params.tags.iter() {
+                    *counts.entry(tag.clone()).or_default() += 1;
+                }
+            }
+            let tags = counts
+                .into_iter()
+                .map(|(tag, count)| TagCount { count, tag })
+                .collect::<Vec<TagCount>>();
+            Ok(SigoDisplay::ListTags(tags))
+        }
+        Command::Projects => {
+            let mut counts = BTreeMap::<String, ProjectCount>::new();
+            for task in read_tasks::<ReadyTask>(tx)? {
+                count_project(&mut counts, &task.active_params, State::Ready);
+            }
+            for task in read_tasks::<WaitingTask>(tx)? {
+                count_project(&mut counts, &task.active_params, State::Waiting);
+            }
+            for task in read_tasks::<CompletedTask>(tx)? {
+                count_project(&mut counts, &task.active_params, State::Completed);
+            }
+            Ok(SigoDisplay::ListProjects(counts.into_values().collect()))
+        }
+        Command::Undo => {
+            let command = undo(tx)?;
+            Ok(SigoDisplay::Undo(command))
+        }
+    }
+}
+
+// a list of ids or a filter applies the command to each sigo on its own,
+// a sigo that fails is left as it was and reported along with the others
+fn for_each_task<F>(tx: &mut Transaction, selector: &Selector, mut f: F) -> Result<SigoDisplay>
+where
+    F: FnMut(&mut Transaction, Task) -> Result<SigoDisplay>,
+{
+    let tasks = match selector {
+        Selector::Task(task_ref) => {
+            let task = Task::get(tx, task_ref)?;
+            return f(tx, task);
+        }
+        Selector::Ids(ranges) => Task::get_by_ids(tx, ranges)?,
+        Selector::Filter(filter) => Task::select(tx, filter)?.into_iter().map(Ok).collect(),
+    };
+    let mut results = Vec::new();
+    for task in tasks {
+        let savepoint = tx.savepoint();
+        let result = task.and_then(|task| f(tx, task));
+        if result.is_err() {
+            tx.rollback(savepoint);
+        }
+        results.push(result);
+    }
+    Ok(SigoDisplay::Bulk(results))
+}
+
+// a filter easily matches more sigos than meant, so changing them is confirmed first
+fn confirm_filter(tx: &Transaction, selector: &Selector, action: &str) -> Result<bool> {
+    let Selector::Filter(filter) = selector else {
+        return Ok(true);
+    };
+    match Task::select(tx, filter)?.len() {
+        0 => Ok(true),
+        count => confirm(&format!("{} {} sigos matching the filter?", action, count)),
+    }
+}
+
+fn keep_tasks(tx: &mut Transaction, selector: &Selector) -> Result<SigoDisplay> {
+    for_each_task(tx, selector, |_, task| {
+        Ok(SigoDisplay::KeepTask(task.active_params().clone()))
+    })
+}
+
+fn modify_task(
+    tx: &mut Transaction,
+    task: Task,
+    modification: &Modification,
+) -> Result<SigoDisplay> {
+    match task {
+        Task::Ready(task) => {
+            let task = task.modify(tx, modification)?;
+            Ok(SigoDisplay::ModifyTask(task.active_params))
+        }
+        Task::Waiting(task) => {
+            let task = task.modify(tx, modification)?;
+            Ok(SigoDisplay::ModifyTask(task.active_params))
+        }
+        Task::Completed(task) => Err(SigoError::TaskAlreadyCompleted(task.active_params.id)),
+    }
+}
+
+fn done_task(tx: &mut Transaction, task: Task) -> Result<SigoDisplay> {
+    match task {
+        Task::Ready(task) => {
+            let task = task.complete(tx)?;
+            Ok(SigoDisplay::CompleteTask(task.active_params))
+        }
+        Task::Waiting(task) => {
+            let task = task.complete(tx)?;
+            Ok(SigoDisplay::CompleteTask(task.active_params))
+        }
+        Task::Completed(task) => Err(SigoError::TaskAlreadyCompleted(task.active_params.id)),
+    }
+}
+
+fn wait_task(tx: &mut Transaction, task: Task, text: &Option<String>) -> Result<SigoDisplay> {
+    match task {
+        Task::Ready(task) => {
+            let task = task.wait(tx, text)?;
+            Ok(SigoDisplay::WaitTask(task.active_params))
+        }
+        Task::Waiting(task) => Ok(SigoDisplay::WaitWaitingTask(task.active_params)),
+        Task::Completed(task) => Err(SigoError::TaskAlreadyCompleted(task.active_params.id)),
+    }
+}
+
+fn back_task(tx: &mut Transaction, task: Task, text: &Option<String>) -> Result<SigoDisplay> {
+    match task {
+        Task::Ready(task) => Ok(SigoDisplay::BackReadyTask(task.active_params)),
+        Task::Waiting(task) => {
+            let task = task.back(tx, text)?;
+            Ok(SigoDisplay::BackTask(task.active_params))
+        }
+        Task::Completed(task) => Err(SigoError::TaskAlreadyCompleted(task.active_params.id)),
+    }
+}
+
+fn annotate_task(
+    tx: &mut Transaction,
+    task: Task,
+    text: &str,
+    edit: Option<usize>,
+) -> Result<SigoDisplay> {
+    match task {
+        Task::Ready(task) => {
+            let task = match edit {
+                Some(number) => task.edit_annotation(tx, number, text)?,
+                None => task.annotate(tx, text)?,
+            };
+            Ok(SigoDisplay::AnnotateTask(task.active_params))
+        }
+        Task::Waiting(task) => {
+            let task = match edit {
+                Some(number) => task.edit_annotation(tx, number, text)?,
+                None => task.annotate(tx, text)?,
+            };
+            Ok(SigoDisplay::AnnotateTask(task.active_params))
+        }
+        Task::Completed(task) => Err(SigoError::TaskAlreadyCompleted(task.active_params.id)),
+    }
+}
+
+// a sigo counts for its project and every parent of it
+fn count_project(counts: &mut BTreeMap<String, ProjectCount>, params: &ActiveParams, state: State) {
+    let Some(project) = &params.project else {
+        return;
+    };
+    let names = project.split('.').collect::<Vec<&str>>();
+    for depth in 1..=names.len() {
+        let node = names[..depth].join(".");
+        let count = counts.entry(node.clone()).or_insert(ProjectCount {
+            ready: 0,
+            waiting: 0,
+            completed: 0,
+            project: node,
+        });
+        match state {
+            State::Ready => count.ready += 1,
+            State::Waiting => count.waiting += 1,
+            State::Completed => count.completed += 1,
+        }
+    }
+}
+
+fn delete_task(tx: &mut Transaction, task: Task, yes: bool) -> Result<SigoDisplay> {
+    match task {
+        Task::Ready(task) => delete_active_task(tx, task, yes),
+        Task::Waiting(task) => delete_active_task(tx, task, yes),
+        Task::Completed(task) => Err(SigoError::TaskAlreadyCompleted(task.active_params.id)),
+    }
+}
+
+fn delete_active_task<T: ActiveFilable>(
+    tx: &mut Transaction,
+    task: T,
+    yes: bool,
+) -> Result<SigoDisplay> {
+    let active_params = task.get_active_params();
+    let question = format!(
+        "Delete sigo {} '{}'?",
+        active_params.id,
+        active_params.get_primary_description()
+    );
+    if !yes && !confirm(&question)? {
+        return Ok(SigoDisplay::KeepTask(active_params));
     }
+    let deleted_task = task.delete(tx)?;
+    Ok(SigoDisplay::DeleteTask(deleted_task.active_params))
 }
@@ -1,9 +1,21 @@
+use std::collections::BTreeMap;
+
+use chrono::Weekday;
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
 pub struct MyConfig {
     pub data: String,
     pub mode: Mode,
+    #[serde(default)]
+    pub urgency: UrgencyConfig,
+    #[serde(default)]
+    pub color: ColorMode,
+    #[serde(default)]
+    pub colors: ColorConfig,
+    #[serde(default)]
+    pub calendar: CalendarConfig,
 }
 
 impl ::std::default::Default for MyConfig {
@@ -16,12 +28,184 @@ impl ::std::default::Default for MyConfig {
                 .into_string()
                 .expect("XDG_DATA_HOME is not set"),
             mode: Mode::Simple,
+            urgency: UrgencyConfig::default(),
+            color: ColorMode::default(),
+            colors: ColorConfig::default(),
+            calendar: CalendarConfig::default(),
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// Weights of the urgency score, a sigo gets the full weight of a factor when
+/// its due date is today or past, or when it is `age_days` old or has waited `waiting_days`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct UrgencyConfig {
+    pub priority_high: f64,
+    pub priority_medium: f64,
+    pub priority_low: f64,
+    pub due: f64,
+    pub due_days: i64,
+    pub overdue: f64,
+    pub age: f64,
+    pub age_days: i64,
+    pub waiting: f64,
+    pub waiting_days: i64,
+    /// Weight added for each tag, like `incident = 5.0`
+    pub tags: BTreeMap<String, f64>,
+}
+
+impl Default for UrgencyConfig {
+    fn default() -> Self {
+        Self {
+            priority_high: 6.0,
+            priority_medium: 3.9,
+            priority_low: 1.8,
+            due: 12.0,
+            due_days: 14,
+            overdue: 3.0,
+            age: 2.0,
+            age_days: 365,
+            waiting: 2.0,
+            waiting_days: 14,
+            tags: BTreeMap::new(),
+        }
+    }
+}
+
+/// Work week of the dates like sow, eow and `in 5 business days`, and of the days
+/// left before a due date. Holidays are listed in the `holidays` file next to config.ini.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CalendarConfig {
+    pub week_start: Weekday,
+    pub week_end: Weekday,
+    pub working_days: Vec<Weekday>,
+}
+
+impl Default for CalendarConfig {
+    fn default() -> Self {
+        Self {
+            week_start: Weekday::Mon,
+            week_end: Weekday::Fri,
+            working_days: vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+            ],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, ValueEnum)]
 pub enum Mode {
     Minimum,
     Simple,
+    /// Machine-readable output, errors are written as JSON to stderr too
+    Json,
+}
+
+/// When to color the sigo tables, `--color` overrides the configured one.
+#[derive(Serialize, Deserialize, Copy, Clone, Default, Debug, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// Only when writing to a terminal and NO_COLOR is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Styles of the rows in the sigo tables, a row gets the style of its priority
+/// and the style of its due date together.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ColorConfig {
+    pub priority_high: Style,
+    pub priority_medium: Style,
+    pub priority_low: Style,
+    pub overdue: Style,
+    pub due_today: Style,
+    pub due_soon: Style,
+    /// Sigos due within this many working days are due soon
+    pub due_soon_days: i64,
+}
+
+impl Default for ColorConfig {
+    fn default() -> Self {
+        Self {
+            priority_high: Style::parse("bold").unwrap(),
+            priority_medium: Style::default(),
+            priority_low: Style::parse("dim").unwrap(),
+            overdue: Style::parse("red").unwrap(),
+            due_today: Style::parse("yellow").unwrap(),
+            due_soon: Style::parse("underline").unwrap(),
+            due_soon_days: 3,
+        }
+    }
+}
+
+/// ANSI style written as space separated words like "bold red".
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub struct Style {
+    spec: String,
+    pub prefix: String,
+    pub suffix: String,
+}
+
+impl Style {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut style = Style {
+            spec: spec.to_string(),
+            ..Style::default()
+        };
+        for word in spec.split_whitespace() {
+            let (prefix, suffix) = match word {
+                "bold" => (1, 22),
+                "dim" => (2, 22),
+                "italic" => (3, 23),
+                "underline" => (4, 24),
+                "reverse" => (7, 27),
+                "black" => (30, 39),
+                "red" => (31, 39),
+                "green" => (32, 39),
+                "yellow" => (33, 39),
+                "blue" => (34, 39),
+                "magenta" => (35, 39),
+                "cyan" => (36, 39),
+                "white" => (37, 39),
+                "none" => continue,
+                _ => return Err(format!("unknown style {:?} in {:?}", word, spec)),
+            };
+            style.prefix.push_str(&format!("\u{1b}[{}m", prefix));
+            style.suffix.insert_str(0, &format!("\u{1b}[{}m", suffix));
+        }
+        Ok(style)
+    }
+
+    // the other style is applied inside this one
+    pub fn compose(&self, other: &Style) -> Style {
+        Style {
+            spec: format!("{} {}", self.spec, other.spec).trim().to_string(),
+            prefix: format!("{}{}", self.prefix, other.prefix),
+            suffix: format!("{}{}", other.suffix, self.suffix),
+        }
+    }
+}
+
+impl TryFrom<String> for Style {
+    type Error = String;
+
+    fn try_from(spec: String) -> Result<Self, Self::Error> {
+        Style::parse(&spec)
+    }
+}
+
+impl From<Style> for String {
+    fn from(style: Style) -> Self {
+        style.spec
+    }
 }
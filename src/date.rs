@@ -1,21 +1,226 @@
-use chrono::{Datelike, Local, NaiveDate};
+use std::{
+    collections::BTreeSet,
+    fs,
+    ops::Bound::{Excluded, Included},
+    path::Path,
+    sync::OnceLock,
+};
+
+use chrono::{Datelike, Days, Local, Months, NaiveDate, Weekday};
+
+use crate::{
+    active_params::Update,
+    config::CalendarConfig,
+    error::{self, SigoError},
+};
+
+// dates are parsed by clap before any command runs, so the calendar is set once up front
+static CALENDAR: OnceLock<Calendar> = OnceLock::new();
+
+/// Work week and holidays, dates are counted in working days with it.
+pub struct Calendar {
+    week_start: Weekday,
+    week_end: Weekday,
+    working_days: [bool; 7],
+    holidays: BTreeSet<NaiveDate>,
+}
+
+impl Calendar {
+    // holidays are yyyy-mm-dd lines, optionally followed by a name, # starts a comment
+    pub fn load(cfg: &CalendarConfig, holidays_path: &Path) -> error::Result<Self> {
+        let mut holidays = BTreeSet::new();
+        if holidays_path.is_file() {
+            let content = fs::read_to_string(holidays_path)
+                .map_err(|e| SigoError::FileReadErr(holidays_path.to_path_buf(), e))?;
+            for (number, line) in content.lines().enumerate() {
+                let line = line.split('#').next().unwrap_or_default();
+                let Some(date) = line.split_whitespace().next() else {
+                    continue;
+                };
+                let holiday = date.parse::<NaiveDate>().map_err(|_| {
+                    SigoError::ParseHolidaysErr(
+                        holidays_path.to_path_buf(),
+                        number + 1,
+                        date.to_string(),
+                    )
+                })?;
+                holidays.insert(holiday);
+            }
+        }
+        Ok(Calendar::new(cfg, holidays))
+    }
+
+    fn new(cfg: &CalendarConfig, holidays: BTreeSet<NaiveDate>) -> Self {
+        let mut working_days = [false; 7];
+        for weekday in cfg.working_days.iter() {
+            working_days[weekday.num_days_from_monday() as usize] = true;
+        }
+        Calendar {
+            week_start: cfg.week_start,
+            week_end: cfg.week_end,
+            working_days,
+            holidays,
+        }
+    }
+
+    pub fn is_working_day(&self, day: NaiveDate) -> bool {
+        self.is_working_weekday(day) && !self.holidays.contains(&day)
+    }
+
+    fn is_working_weekday(&self, day: NaiveDate) -> bool {
+        self.working_days[day.weekday().num_days_from_monday() as usize]
+    }
+
+    /// Working days after `from` up to and including `to`, negative when `to` is earlier.
+    pub fn working_days_until(&self, from: NaiveDate, to: NaiveDate) -> i64 {
+        if to < from {
+            return -self.working_days_until(to, from);
+        }
+        let weeks = (to - from).num_days() / 7;
+        let per_week = self.working_days.iter().filter(|working| **working).count() as i64;
+        let rest = (from + Days::new(weeks as u64 * 7))
+            .iter_days()
+            .skip(1)
+            .take_while(|day| *day <= to)
+            .filter(|day| self.is_working_weekday(*day))
+            .count() as i64;
+        let holidays = self
+            .holidays
+            .range((Excluded(from), Included(to)))
+            .filter(|day| self.is_working_weekday(**day))
+            .count() as i64;
+        weeks * per_week + rest - holidays
+    }
+}
+
+impl Default for Calendar {
+    fn default() -> Self {
+        Calendar::new(&CalendarConfig::default(), BTreeSet::new())
+    }
+}
+
+pub fn set_calendar(calendar: Calendar) {
+    let _ = CALENDAR.set(calendar);
+}
+
+pub fn calendar() -> &'static Calendar {
+    CALENDAR.get_or_init(Calendar::default)
+}
+
+const DATE_FORMS: &str = "dates are yyyy-mm-dd, mm-dd, dd, today, tomorrow, +3d, +2w, \
+in 5 days, in 5 business days, weekdays like fri or next mon, sow, som, eow, eom, eoq or eoy";
 
 // strが日付のフォーマットに準拠しているかチェックする(ex: 2024-07-20)
 pub fn validate_date_str(date_str: &str) -> Result<NaiveDate, String> {
     let today = Local::now().naive_local().date();
-    match date_str {
-        "today" => Ok(today),
-        "eow" => Ok(last_day_of_week(today)),
-        "eom" => Ok(last_day_of_month(today)),
-        _ => match date_str.parse::<NaiveDate>() {
-            Ok(naive_date) => Ok(naive_date),
-            Err(_e) => Err("The date value is invalid format, not yyyy-mm-dd.".to_string()),
-        },
+    parse_date(&date_str.trim().to_lowercase(), today, calendar())
+        .ok_or_else(|| format!("'{}' is not a date, {}", date_str, DATE_FORMS))
+}
+
+// same as validate_date_str, but also accepts "none" to clear the due date
+pub fn validate_due_update(date_str: &str) -> Result<Update<NaiveDate>, String> {
+    Update::parse(date_str, validate_date_str)
+}
+
+fn parse_date(s: &str, today: NaiveDate, calendar: &Calendar) -> Option<NaiveDate> {
+    match s {
+        "today" => return Some(today),
+        "tomorrow" => return today.succ_opt(),
+        "sow" => return Some(today.week(calendar.week_start).first_day()),
+        "eow" => return Some(last_working_day_of_week(today, calendar)),
+        "som" => return today.with_day(1),
+        "eom" => return Some(last_day_of_month(today)),
+        "eoq" => return Some(last_day_of_quarter(today)),
+        "eoy" => return NaiveDate::from_ymd_opt(today.year(), 12, 31),
+        _ => {}
+    }
+    if let Some(offset) = s.strip_prefix('+').or_else(|| s.strip_prefix("in ")) {
+        return add_offset(today, offset, calendar);
+    }
+    if let Some(weekday) = s.strip_prefix("next ") {
+        let weekday = weekday.trim().parse::<Weekday>().ok()?;
+        return next_weekday(today, weekday).checked_add_days(Days::new(7));
+    }
+    if let Ok(weekday) = s.parse::<Weekday>() {
+        return Some(next_weekday(today, weekday));
+    }
+    if let Ok(date) = s.parse::<NaiveDate>() {
+        return Some(date);
     }
+    next_partial_date(s, today)
 }
 
-fn last_day_of_week(day: NaiveDate) -> NaiveDate {
-    day.week(chrono::Weekday::Sat).last_day()
+// offsets like 3d, 2w, 5 days or 5 business days
+fn add_offset(day: NaiveDate, offset: &str, calendar: &Calendar) -> Option<NaiveDate> {
+    let digits = offset
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(offset.len());
+    let count: u64 = offset[..digits].parse().ok()?;
+    match offset[digits..].trim() {
+        "d" | "day" | "days" => day.checked_add_days(Days::new(count)),
+        "w" | "week" | "weeks" => day.checked_add_days(Days::new(count.checked_mul(7)?)),
+        "business day" | "business days" => add_working_days(day, count, calendar),
+        _ => None,
+    }
+}
+
+fn add_working_days(mut day: NaiveDate, count: u64, calendar: &Calendar) -> Option<NaiveDate> {
+    if !calendar.working_days.contains(&true) {
+        return None;
+    }
+    let mut left = count;
+    while left > 0 {
+        day = day.succ_opt()?;
+        if calendar.is_working_day(day) {
+            left -= 1;
+        }
+    }
+    Some(day)
+}
+
+// the weekday on or after the day
+fn next_weekday(day: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let days = (7 + weekday.num_days_from_monday() - day.weekday().num_days_from_monday()) % 7;
+    day + Days::new(days as u64)
+}
+
+// mm-dd or dd, the first such date on or after the day
+fn next_partial_date(s: &str, day: NaiveDate) -> Option<NaiveDate> {
+    let number = |part: &str| {
+        if (1..=2).contains(&part.len()) && part.bytes().all(|b| b.is_ascii_digit()) {
+            part.parse::<u32>().ok()
+        } else {
+            None
+        }
+    };
+    match s.split_once('-') {
+        // eight years always include a leap year for 02-29
+        Some((month, date)) => {
+            let (month, date) = (number(month)?, number(date)?);
+            (day.year()..=day.year() + 8)
+                .filter_map(|year| NaiveDate::from_ymd_opt(year, month, date))
+                .find(|candidate| *candidate >= day)
+        }
+        None => {
+            let date = number(s)?;
+            let first = day.with_day(1)?;
+            (0..12)
+                .filter_map(|months| first.checked_add_months(Months::new(months)))
+                .filter_map(|month| month.with_day(date))
+                .find(|candidate| *candidate >= day)
+        }
+    }
+}
+
+// the last working day up to the end of the week, which is the next one when this week is over
+fn last_working_day_of_week(day: NaiveDate, calendar: &Calendar) -> NaiveDate {
+    let mut end = next_weekday(day, calendar.week_end);
+    while end > day && !calendar.is_working_day(end) {
+        end = end
+            .pred_opt()
+            .expect("The day before the end of week does always exist");
+    }
+    end
 }
 
 fn last_day_of_month(day: NaiveDate) -> NaiveDate {
@@ -24,3 +229,134 @@ fn last_day_of_month(day: NaiveDate) -> NaiveDate {
         .pred_opt()
         .expect("The end of this month does always exist")
 }
+
+fn last_day_of_quarter(day: NaiveDate) -> NaiveDate {
+    let last_month = (day.month0() / 3) * 3 + 3;
+    last_day_of_month(
+        NaiveDate::from_ymd_opt(day.year(), last_month, 1)
+            .expect("The last month of this quarter does always exist"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn calendar_with_holidays(holidays: &[&str]) -> Calendar {
+        let holidays = holidays.iter().map(|holiday| date(holiday)).collect();
+        Calendar::new(&CalendarConfig::default(), holidays)
+    }
+
+    // 2026-10-18 is a sunday
+    fn parse(s: &str, today: &str) -> Option<NaiveDate> {
+        parse_date(s, date(today), &Calendar::default())
+    }
+
+    #[test]
+    fn weekdays_are_the_next_one_today_included() {
+        assert_eq!(parse("sun", "2026-10-18"), Some(date("2026-10-18")));
+        assert_eq!(parse("mon", "2026-10-18"), Some(date("2026-10-19")));
+        assert_eq!(parse("friday", "2026-10-18"), Some(date("2026-10-23")));
+        assert_eq!(parse("next mon", "2026-10-18"), Some(date("2026-10-26")));
+        assert_eq!(parse("next sun", "2026-10-18"), Some(date("2026-10-25")));
+    }
+
+    #[test]
+    fn relative_offsets() {
+        assert_eq!(parse("tomorrow", "2026-12-31"), Some(date("2027-01-01")));
+        assert_eq!(parse("+3d", "2026-10-18"), Some(date("2026-10-21")));
+        assert_eq!(parse("+2w", "2026-10-18"), Some(date("2026-11-01")));
+        assert_eq!(parse("in 1 day", "2026-10-18"), Some(date("2026-10-19")));
+        assert_eq!(parse("+3x", "2026-10-18"), None);
+    }
+
+    #[test]
+    fn partial_dates_roll_over_to_the_next_match() {
+        assert_eq!(parse("10-18", "2026-10-18"), Some(date("2026-10-18")));
+        assert_eq!(parse("07-20", "2026-10-18"), Some(date("2027-07-20")));
+        assert_eq!(parse("02-29", "2026-10-18"), Some(date("2028-02-29")));
+        assert_eq!(parse("20", "2026-10-18"), Some(date("2026-10-20")));
+        assert_eq!(parse("15", "2026-10-18"), Some(date("2026-11-15")));
+        assert_eq!(parse("31", "2026-11-05"), Some(date("2026-12-31")));
+        assert_eq!(parse("13-01", "2026-10-18"), None);
+        assert_eq!(parse("32", "2026-10-18"), None);
+        assert_eq!(parse("02-30", "2026-10-18"), None);
+    }
+
+    #[test]
+    fn ends_of_periods() {
+        assert_eq!(parse("som", "2026-10-18"), Some(date("2026-10-01")));
+        assert_eq!(parse("eom", "2026-12-18"), Some(date("2026-12-31")));
+        assert_eq!(parse("eoq", "2026-10-18"), Some(date("2026-12-31")));
+        assert_eq!(parse("eoq", "2026-02-01"), Some(date("2026-03-31")));
+        assert_eq!(parse("eoy", "2026-10-18"), Some(date("2026-12-31")));
+        assert_eq!(parse("sow", "2026-10-18"), Some(date("2026-10-12")));
+    }
+
+    #[test]
+    fn business_days_skip_weekends_and_holidays() {
+        let today = date("2026-10-18");
+        let calendar = calendar_with_holidays(&[]);
+        assert_eq!(
+            parse_date("in 5 business days", today, &calendar),
+            Some(date("2026-10-23"))
+        );
+        let calendar = calendar_with_holidays(&["2026-10-20", "2026-10-24"]);
+        assert_eq!(
+            parse_date("in 5 business days", today, &calendar),
+            Some(date("2026-10-26"))
+        );
+        assert_eq!(
+            parse_date("in 0 business days", today, &calendar),
+            Some(today)
+        );
+    }
+
+    #[test]
+    fn end_of_week_on_a_weekend_is_next_friday() {
+        let calendar = calendar_with_holidays(&[]);
+        for today in ["2026-10-17", "2026-10-18", "2026-10-23"] {
+            assert_eq!(
+                parse_date("eow", date(today), &calendar),
+                Some(date("2026-10-23"))
+            );
+        }
+        let calendar = calendar_with_holidays(&["2026-10-23"]);
+        assert_eq!(
+            parse_date("eow", date("2026-10-18"), &calendar),
+            Some(date("2026-10-22"))
+        );
+    }
+
+    #[test]
+    fn working_days_until_round_trips() {
+        let calendar = calendar_with_holidays(&["2026-10-20", "2026-12-25"]);
+        let from = date("2026-10-18");
+        for days in 0..120 {
+            let to = from + Days::new(days);
+            let counted = from
+                .iter_days()
+                .skip(1)
+                .take_while(|day| *day <= to)
+                .filter(|day| calendar.is_working_day(*day))
+                .count() as i64;
+            assert_eq!(calendar.working_days_until(from, to), counted);
+            assert_eq!(calendar.working_days_until(to, from), -counted);
+        }
+        for count in 0..60 {
+            let to = add_working_days(from, count, &calendar).unwrap();
+            assert_eq!(calendar.working_days_until(from, to), count as i64);
+        }
+    }
+
+    #[test]
+    fn invalid_dates_list_the_accepted_forms() {
+        let err = validate_date_str("someday").unwrap_err();
+        assert!(err.contains("'someday' is not a date"));
+        assert!(err.contains("in 5 business days"));
+    }
+}
@@ -1,100 +1,310 @@
 pub enum SigoDisplay {
-    CreateReadyTask(u32),
-    CreateWaitingTask(u32),
-    ModifyTask(u32, String),
-    CompleteTask(u32, String),
-    WaitTask(u32, String),
-    WaitWaitingTask(u32, String),
-    BackTask(u32, String),
-    BackReadyTask(u32, String),
-    AnnotateTask(u32, String),
-    ListReadyTasks(Vec<ReadyTask>),
-    ListWaitingTasks(Vec<WaitingTask>),
+    CreateReadyTask(u32, Uuid),
+    CreateWaitingTask(u32, Uuid),
+    ModifyTask(ActiveParams),
+    EditTask(ActiveParams),
+    CompleteTask(ActiveParams),
+    DeleteTask(ActiveParams),
+    KeepTask(ActiveParams),
+    WaitTask(ActiveParams),
+    WaitWaitingTask(ActiveParams),
+    BackTask(ActiveParams),
+    BackReadyTask(ActiveParams),
+    AnnotateTask(ActiveParams),
+    DenotateTask(ActiveParams),
+    ReopenTask(ActiveParams),
+    ReopenWaitingTask(ActiveParams),
+    TaskInfo(Task),
+    ListReadyTasks(Vec<Urgent<ReadyTask>>),
+    ListWaitingTasks(Vec<Urgent<WaitingTask>>),
+    ListCompletedTasks(Vec<CompletedTask>),
+    ListTags(Vec<TagCount>),
+    ListProjects(Vec<ProjectCount>),
+    Bulk(Vec<Result<SigoDisplay>>),
+    Undo(String),
+}
+
+/// A row of `sigo tags`.
+#[derive(Tabled, Serialize)]
+pub struct TagCount {
+    pub count: usize,
+    #[tabled(display_with = "display_tag")]
+    pub tag: String,
+}
+
+/// A row of `sigo projects`, counting the sigos of subprojects too.
+#[derive(Tabled, Serialize)]
+pub struct ProjectCount {
+    pub ready: usize,
+    pub waiting: usize,
+    pub completed: usize,
+    pub project: String,
+}
+
+fn display_tag(tag: &str) -> String {
+    format!("+{}", tag)
 }
 
 use std::fmt::{self, Display};
 
+use serde::Serialize;
+use serde_json::{json, Value};
+use tabled::Tabled;
+use uuid::Uuid;
+
 use crate::{
+    active_params::{ActiveParams, State},
     config::{Mode, MyConfig},
-    task::{ReadyTask, WaitingTask},
-    utils::tasks_to_string,
+    error::{Result, SigoError},
+    task::{CompletedTask, ReadyTask, Task, WaitingTask},
+    urgency::Urgent,
+    utils::{
+        display_option_date, display_option_datetime, display_option_priority,
+        display_option_string, display_tags, tasks_to_string,
+    },
 };
 
 impl SigoDisplay {
-    pub fn display<'a>(&'a self, cfg: &MyConfig) -> Box<dyn Display + 'a> {
+    pub fn display<'a>(&'a self, cfg: &'a MyConfig) -> Box<dyn Display + 'a> {
         match cfg.mode {
-            Mode::Minimum => Box::new(DisplayMinimum(self)),
-            Mode::Simple => Box::new(DisplaySimple(self)),
+            Mode::Minimum => Box::new(DisplayMinimum(self, cfg)),
+            Mode::Simple => Box::new(DisplaySimple(self, cfg)),
+            Mode::Json => Box::new(DisplayJson(self)),
         }
     }
+
+    // a bulk command fails with its first failing sigo
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            SigoDisplay::Bulk(results) => results
+                .iter()
+                .find_map(|result| result.as_ref().err())
+                .map_or(0, SigoError::exit_code),
+            _ => 0,
+        }
+    }
+
+    // results of a command are objects with an action, lists are arrays of sigos
+    fn to_json(&self) -> Value {
+        match self {
+            SigoDisplay::CreateReadyTask(id, uuid) => {
+                json!({"action": "create", "state": State::Ready, "id": id, "uuid": uuid})
+            }
+            SigoDisplay::CreateWaitingTask(id, uuid) => {
+                json!({"action": "create", "state": State::Waiting, "id": id, "uuid": uuid})
+            }
+            SigoDisplay::ModifyTask(params) => action_json("modify", params),
+            SigoDisplay::EditTask(params) => action_json("edit", params),
+            SigoDisplay::CompleteTask(params) => action_json("complete", params),
+            SigoDisplay::DeleteTask(params) => action_json("delete", params),
+            SigoDisplay::KeepTask(params) => action_json("keep", params),
+            SigoDisplay::WaitTask(params) => action_json("wait", params),
+            SigoDisplay::WaitWaitingTask(params) => action_json("already_waiting", params),
+            SigoDisplay::BackTask(params) => action_json("back", params),
+            SigoDisplay::BackReadyTask(params) => action_json("already_ready", params),
+            SigoDisplay::AnnotateTask(params) => action_json("annotate", params),
+            SigoDisplay::DenotateTask(params) => action_json("denotate", params),
+            SigoDisplay::ReopenTask(params) => action_json("reopen", params),
+            SigoDisplay::ReopenWaitingTask(params) => action_json("reopen", params),
+            SigoDisplay::TaskInfo(task) => {
+                let mut task_json = task_json(task.active_params(), task.state());
+                if let Task::Completed(task) = task {
+                    task_json["completed_at"] = json!(task.completed_at);
+                }
+                json!({"action": "info", "task": task_json})
+            }
+            SigoDisplay::ListReadyTasks(tasks) => tasks
+                .iter()
+                .map(|t| {
+                    let mut task_json = task_json(&t.task.active_params, State::Ready);
+                    task_json["urgency"] = json!(t.urgency);
+                    task_json
+                })
+                .collect(),
+            SigoDisplay::ListWaitingTasks(tasks) => tasks
+                .iter()
+                .map(|t| {
+                    let mut task_json = task_json(&t.task.active_params, State::Waiting);
+                    task_json["urgency"] = json!(t.urgency);
+                    task_json
+                })
+                .collect(),
+            SigoDisplay::ListCompletedTasks(tasks) => tasks
+                .iter()
+                .map(|t| {
+                    let mut task_json = task_json(&t.active_params, State::Completed);
+                    task_json["completed_at"] = json!(t.completed_at);
+                    task_json
+                })
+                .collect(),
+            SigoDisplay::ListTags(tags) => json!(tags),
+            SigoDisplay::ListProjects(projects) => json!(projects),
+            SigoDisplay::Bulk(results) => {
+                let results = results
+                    .iter()
+                    .map(|result| match result {
+                        Ok(output) => output.to_json(),
+                        Err(err) => err.to_json(),
+                    })
+                    .collect::<Vec<Value>>();
+                json!({"action": "bulk", "results": results})
+            }
+            SigoDisplay::Undo(command) => json!({"action": "undo", "command": command}),
+        }
+    }
+}
+
+fn action_json(action: &str, params: &ActiveParams) -> Value {
+    json!({"action": action, "task": params})
+}
+
+fn task_json(params: &ActiveParams, state: State) -> Value {
+    let mut task_json = json!(params);
+    task_json["state"] = json!(state);
+    task_json
+}
+
+pub struct DisplayJson<'a>(&'a SigoDisplay);
+impl fmt::Display for DisplayJson<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let json = serde_json::to_string_pretty(&self.0.to_json()).map_err(|_| fmt::Error)?;
+        write!(f, "{}", json)
+    }
 }
 
-pub struct DisplayMinimum<'a>(&'a SigoDisplay);
+pub struct DisplayMinimum<'a>(&'a SigoDisplay, &'a MyConfig);
 impl fmt::Display for DisplayMinimum<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.0 {
-            SigoDisplay::CreateReadyTask(id) => {
+            SigoDisplay::CreateReadyTask(id, _) => {
                 writeln!(f, "Created sigo {}", id)
             }
-            SigoDisplay::CreateWaitingTask(id) => {
+            SigoDisplay::CreateWaitingTask(id, _) => {
                 writeln!(f, "Created waiting sigo {}", id)
             }
-            SigoDisplay::ModifyTask(id, description) => {
+            SigoDisplay::ModifyTask(ActiveParams {
+                id, description, ..
+            }) => {
                 writeln!(f, "Modify sigo {} '{}'", id, description)
             }
-            SigoDisplay::CompleteTask(id, description) => {
+            SigoDisplay::EditTask(ActiveParams {
+                id, description, ..
+            }) => {
+                writeln!(f, "Edited sigo {} '{}'", id, description)
+            }
+            SigoDisplay::CompleteTask(ActiveParams {
+                id, description, ..
+            }) => {
                 writeln!(f, "Complete sigo {} '{}'", id, description)
             }
-            SigoDisplay::WaitTask(id, description) => {
+            SigoDisplay::DeleteTask(ActiveParams {
+                id, description, ..
+            }) => {
+                writeln!(f, "Deleted sigo {} '{}'", id, description)
+            }
+            SigoDisplay::KeepTask(ActiveParams {
+                id, description, ..
+            }) => {
+                writeln!(f, "Kept sigo {} '{}'", id, description)
+            }
+            SigoDisplay::WaitTask(ActiveParams {
+                id, description, ..
+            }) => {
                 writeln!(f, "Waiting sigo {} '{}'", id, description)
             }
-            SigoDisplay::WaitWaitingTask(id, description) => {
+            SigoDisplay::WaitWaitingTask(ActiveParams {
+                id, description, ..
+            }) => {
                 writeln!(f, "Already waiting sigo {} '{}'", id, description)
             }
-            SigoDisplay::BackTask(id, description) => {
+            SigoDisplay::BackTask(ActiveParams {
+                id, description, ..
+            }) => {
                 writeln!(f, "Returning sigo {} '{}'", id, description)
             }
-            SigoDisplay::BackReadyTask(id, description) => {
+            SigoDisplay::BackReadyTask(ActiveParams {
+                id, description, ..
+            }) => {
                 writeln!(f, "Already ready sigo {} '{}'", id, description)
             }
-            SigoDisplay::AnnotateTask(id, description) => {
+            SigoDisplay::AnnotateTask(ActiveParams {
+                id, description, ..
+            }) => {
                 writeln!(f, "Annotated sigo {} '{}'", id, description)
             }
+            SigoDisplay::DenotateTask(ActiveParams {
+                id, description, ..
+            }) => {
+                writeln!(f, "Denotated sigo {} '{}'", id, description)
+            }
+            SigoDisplay::ReopenTask(ActiveParams {
+                id, description, ..
+            }) => {
+                writeln!(f, "Reopened sigo {} '{}'", id, description)
+            }
+            SigoDisplay::ReopenWaitingTask(ActiveParams {
+                id, description, ..
+            }) => {
+                writeln!(f, "Reopened waiting sigo {} '{}'", id, description)
+            }
+            SigoDisplay::TaskInfo(task) => {
+                write!(f, "{}", task_info(task))
+            }
             SigoDisplay::ListReadyTasks(tasks) => {
-                writeln!(f, "{}", tasks_to_string(tasks))
+                writeln!(f, "{}", tasks_to_string(tasks, self.1))
             }
             SigoDisplay::ListWaitingTasks(tasks) => {
-                writeln!(f, "{}", tasks_to_string(tasks))
+                writeln!(f, "{}", tasks_to_string(tasks, self.1))
+            }
+            SigoDisplay::ListCompletedTasks(tasks) => {
+                writeln!(f, "{}", tasks_to_string(tasks, self.1))
+            }
+            SigoDisplay::ListTags(tags) => {
+                writeln!(f, "{}", tasks_to_string(tags, self.1))
+            }
+            SigoDisplay::ListProjects(projects) => {
+                writeln!(f, "{}", tasks_to_string(projects, self.1))
+            }
+            SigoDisplay::Bulk(results) => {
+                if results.is_empty() {
+                    return writeln!(f, "No matches.");
+                }
+                write!(f, "{}", bulk_results(results, self.1))
+            }
+            SigoDisplay::Undo(command) => {
+                writeln!(f, "Undo 'sigo {}'", command)
             }
         }
     }
 }
 
-pub struct DisplaySimple<'a>(&'a SigoDisplay);
+pub struct DisplaySimple<'a>(&'a SigoDisplay, &'a MyConfig);
 impl fmt::Display for DisplaySimple<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.0 {
-            SigoDisplay::CreateReadyTask(id) => {
+            SigoDisplay::CreateReadyTask(id, uuid) => {
                 writeln!(
                     f,
-                    "✅ Created sigo {0}.
+                    "✅ Created sigo {0} ({1}).
 
     (use \"sigo done {0}\" to complete sigo)
     (use \"sigo wait {0}\" to change sigo waiting)",
-                    id
+                    id, uuid
                 )
             }
-            SigoDisplay::CreateWaitingTask(id) => {
+            SigoDisplay::CreateWaitingTask(id, uuid) => {
                 writeln!(
                     f,
-                    "✅ Created waiting sigo {0}.
+                    "✅ Created waiting sigo {0} ({1}).
 
     (use \"sigo done {0}\" to complete sigo)
     (use \"sigo back {0}\" to change sigo ready)",
-                    id
+                    id, uuid
                 )
             }
-            SigoDisplay::ModifyTask(id, description) => {
+            SigoDisplay::ModifyTask(ActiveParams {
+                id, description, ..
+            }) => {
                 writeln!(
                     f,
                     "✅ Modify sigo {0} '{1}'.
@@ -103,7 +313,20 @@ impl fmt::Display for DisplaySimple<'_> {
                     id, description
                 )
             }
-            SigoDisplay::CompleteTask(id, description) => {
+            SigoDisplay::EditTask(ActiveParams {
+                id, description, ..
+            }) => {
+                writeln!(
+                    f,
+                    "✅ Edited sigo {0} '{1}'.
+
+    (use \"sigo done {0}\" to complete sigo)",
+                    id, description
+                )
+            }
+            SigoDisplay::CompleteTask(ActiveParams {
+                id, description, ..
+            }) => {
                 writeln!(
                     f,
                     "✅ Complete sigo {} '{}'.
@@ -113,21 +336,95 @@ impl fmt::Display for DisplaySimple<'_> {
                     id, description
                 )
             }
-            SigoDisplay::WaitTask(id, description) => {
+            SigoDisplay::DeleteTask(ActiveParams {
+                id, description, ..
+            }) => {
+                writeln!(
+                    f,
+                    "✅ Deleted sigo {} '{}'.
+
+    (use \"sigo undo\" to restore sigo)",
+                    id, description
+                )
+            }
+            SigoDisplay::KeepTask(ActiveParams {
+                id, description, ..
+            }) => {
+                writeln!(f, "Kept sigo {} '{}'.", id, description)
+            }
+            SigoDisplay::WaitTask(ActiveParams {
+                id, description, ..
+            }) => {
                 writeln!(f, "✅ Waiting sigo {} '{}'.", id, description)
             }
-            SigoDisplay::WaitWaitingTask(id, description) => {
+            SigoDisplay::WaitWaitingTask(ActiveParams {
+                id, description, ..
+            }) => {
                 writeln!(f, "✅ Already waiting sigo {} '{}'.", id, description)
             }
-            SigoDisplay::BackTask(id, description) => {
+            SigoDisplay::BackTask(ActiveParams {
+                id, description, ..
+            }) => {
                 writeln!(f, "✅ Returning sigo {} '{}'.", id, description)
             }
-            SigoDisplay::BackReadyTask(id, description) => {
+            SigoDisplay::BackReadyTask(ActiveParams {
+                id, description, ..
+            }) => {
                 writeln!(f, "✅ Already ready sigo {} '{}'.", id, description)
             }
-            SigoDisplay::AnnotateTask(id, description) => {
+            SigoDisplay::AnnotateTask(ActiveParams {
+                id, description, ..
+            }) => {
                 writeln!(f, "✅ Annotated sigo {} '{}'.", id, description)
             }
+            SigoDisplay::DenotateTask(ActiveParams {
+                id, description, ..
+            }) => {
+                writeln!(f, "✅ Denotated sigo {} '{}'.", id, description)
+            }
+            SigoDisplay::ReopenTask(ActiveParams {
+                id, description, ..
+            }) => {
+                writeln!(
+                    f,
+                    "✅ Reopened sigo {0} '{1}'.
+
+    (use \"sigo done {0}\" to complete sigo)
+    (use \"sigo wait {0}\" to change sigo waiting)",
+                    id, description
+                )
+            }
+            SigoDisplay::ReopenWaitingTask(ActiveParams {
+                id, description, ..
+            }) => {
+                writeln!(
+                    f,
+                    "✅ Reopened waiting sigo {0} '{1}'.
+
+    (use \"sigo done {0}\" to complete sigo)
+    (use \"sigo back {0}\" to change sigo ready)",
+                    id, description
+                )
+            }
+            SigoDisplay::TaskInfo(task) => {
+                let id = task.active_params().id;
+                let hint = match task {
+                    Task::Ready(_) => format!(
+                        "(use \"sigo done {0}\" to complete sigo)
+    (use \"sigo wait {0}\" to change sigo waiting)",
+                        id
+                    ),
+                    Task::Waiting(_) => format!(
+                        "(use \"sigo done {0}\" to complete sigo)
+    (use \"sigo back {0}\" to change sigo ready)",
+                        id
+                    ),
+                    Task::Completed(_) => {
+                        format!("(use \"sigo reopen {}\" to reopen sigo)", id)
+                    }
+                };
+                writeln!(f, "{}\n    {}", task_info(task), hint)
+            }
             SigoDisplay::ListReadyTasks(tasks) => {
                 let tasks_len = tasks.len();
                 if tasks_len == 0 {
@@ -145,7 +442,7 @@ impl fmt::Display for DisplaySimple<'_> {
 {} sigos
     (use \"sigo done\" to complete sigo)
     (use \"sigo waiting\" to list waiting sigos)",
-                        tasks_to_string(tasks),
+                        tasks_to_string(tasks, self.1),
                         tasks.len()
                     )
                 }
@@ -167,11 +464,165 @@ impl fmt::Display for DisplaySimple<'_> {
 {} sigos
     (use \"sigo done\" to complete sigo)
     (use \"sigo list\" to list ready sigos)",
-                        tasks_to_string(tasks),
+                        tasks_to_string(tasks, self.1),
                         tasks.len()
                     )
                 }
             }
+            SigoDisplay::ListCompletedTasks(tasks) => {
+                let tasks_len = tasks.len();
+                if tasks_len == 0 {
+                    writeln!(
+                        f,
+                        "No matches.
+    (use \"sigo list\" to list ready sigos)"
+                    )
+                } else {
+                    writeln!(
+                        f,
+                        "{}
+
+{} sigos
+    (use \"sigo list\" to list ready sigos)",
+                        tasks_to_string(tasks, self.1),
+                        tasks.len()
+                    )
+                }
+            }
+            SigoDisplay::ListTags(tags) => {
+                if tags.is_empty() {
+                    writeln!(
+                        f,
+                        "No tags.
+    (use \"sigo modify <id> +tag\" to tag sigo)"
+                    )
+                } else {
+                    writeln!(
+                        f,
+                        "{}
+
+{} tags
+    (use \"sigo list +tag\" to list ready sigos with the tag)",
+                        tasks_to_string(tags, self.1),
+                        tags.len()
+                    )
+                }
+            }
+            SigoDisplay::ListProjects(projects) => {
+                if projects.is_empty() {
+                    writeln!(
+                        f,
+                        "No projects.
+    (use \"sigo modify <id> --project <name>\" to put sigo in a project)"
+                    )
+                } else {
+                    writeln!(
+                        f,
+                        "{}
+
+{} projects
+    (use \"sigo list project:<name>\" to list ready sigos in the project)",
+                        tasks_to_string(projects, self.1),
+                        projects.len()
+                    )
+                }
+            }
+            SigoDisplay::Bulk(results) => {
+                if results.is_empty() {
+                    return writeln!(f, "No matches.");
+                }
+                let failed = results.iter().filter(|r| r.is_err()).count();
+                let kept = results
+                    .iter()
+                    .filter(|r| matches!(r, Ok(SigoDisplay::KeepTask(_))))
+                    .count();
+                let succeeded = results.len() - failed - kept;
+                write!(
+                    f,
+                    "{}
+{} succeeded, {} failed",
+                    bulk_results(results, self.1),
+                    succeeded,
+                    failed
+                )?;
+                if kept > 0 {
+                    write!(f, ", {} kept", kept)?;
+                }
+                writeln!(f)?;
+                if succeeded > 0 {
+                    writeln!(f, "    (use \"sigo undo\" to revert them all)")?;
+                }
+                Ok(())
+            }
+            SigoDisplay::Undo(command) => {
+                writeln!(
+                    f,
+                    "✅ Undo 'sigo {}'.
+
+    (use \"sigo undo\" again to undo the previous change)",
+                    command
+                )
+            }
         }
     }
 }
+
+// one line per sigo, failures say why the sigo was left as it was
+fn bulk_results(results: &[Result<SigoDisplay>], cfg: &MyConfig) -> String {
+    results
+        .iter()
+        .map(|result| match result {
+            Ok(output) => DisplayMinimum(output, cfg).to_string(),
+            Err(err) => format!("Error: {}", err),
+        })
+        .collect()
+}
+
+fn task_info(task: &Task) -> String {
+    let params = task.active_params();
+    let mut lines = vec![
+        ("id", params.id.to_string()),
+        ("uuid", params.uuid.to_string()),
+        ("state", task.state().to_string()),
+        ("description", params.description.clone()),
+        ("priority", display_option_priority(&params.priority)),
+        ("due", display_option_date(&params.due)),
+        ("project", display_option_string(&params.project)),
+        ("tags", display_tags(&params.tags)),
+        ("entry", display_option_datetime(&params.entry)),
+        ("modified", display_option_datetime(&params.modified)),
+    ];
+    if let Task::Completed(task) = task {
+        lines.push(("completed", display_option_datetime(&task.completed_at)));
+    }
+    for (index, annotation) in params.annotations.iter().enumerate() {
+        let label = if index == 0 { "annotations" } else { "" };
+        lines.push((
+            label,
+            match annotation.entry {
+                Some(entry) => format!(
+                    "{}. {} {}",
+                    index + 1,
+                    display_option_datetime(&Some(entry)),
+                    annotation.text
+                ),
+                None => format!("{}. {}", index + 1, annotation.text),
+            },
+        ));
+    }
+    for (index, change) in params.history.iter().enumerate() {
+        let label = if index == 0 { "history" } else { "" };
+        lines.push((
+            label,
+            format!(
+                "{} {}",
+                display_option_datetime(&Some(change.at)),
+                change.state
+            ),
+        ));
+    }
+    lines
+        .into_iter()
+        .map(|(label, value)| format!("{:<12} {}\n", label, value))
+        .collect()
+}
@@ -0,0 +1,243 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    active_params::{parse_project, ActiveParams, TagChange, Update},
+    error::{Result, SigoError},
+    Priority,
+};
+
+const FRONT_MATTER_DELIMITER: &str = "+++";
+const ERROR_PREFIX: &str = "# error: ";
+
+/// The fields of a sigo written as TOML front matter in the edited file.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FrontMatter {
+    description: String,
+    priority: Option<Priority>,
+    due: Option<NaiveDate>,
+    project: Option<String>,
+    #[serde(default)]
+    tags: BTreeSet<String>,
+}
+
+// Open the sigo in $VISUAL or $EDITOR and return the edited params.
+// The editor is reopened with the error on top until the file parses.
+pub fn edit(active_params: &ActiveParams) -> Result<ActiveParams> {
+    let path = create_temp_file()?;
+    let result = edit_file(active_params, &path);
+    let _ = fs::remove_file(&path);
+    result
+}
+
+// Create an empty file nobody else can have opened, so concurrent edits never share one
+// and a planted symlink is not followed.
+fn create_temp_file() -> Result<PathBuf> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let path = std::env::temp_dir().join(format!(
+            "sigo-{}-{}-{}.md",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            &Uuid::new_v4().simple().to_string()[..8]
+        ));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => return Ok(path),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(SigoError::FileWriteErr(path, e)),
+        }
+    }
+}
+
+fn edit_file(active_params: &ActiveParams, path: &Path) -> Result<ActiveParams> {
+    let mut contents = to_document(active_params)?;
+    loop {
+        fs::write(path, &contents).map_err(|e| SigoError::FileWriteErr(path.to_path_buf(), e))?;
+        open_editor(path)?;
+        let edited =
+            fs::read_to_string(path).map_err(|e| SigoError::FileReadErr(path.to_path_buf(), e))?;
+        let edited = strip_errors(&edited);
+        if edited.trim().is_empty() {
+            return Err(SigoError::EditAborted(active_params.id));
+        }
+        match from_document(active_params, &edited) {
+            Ok(edited_params) => return Ok(edited_params),
+            Err(message) => {
+                contents = message
+                    .lines()
+                    .map(|line| format!("{}{}\n", ERROR_PREFIX, line))
+                    .collect::<String>()
+                    + &edited
+            }
+        }
+    }
+}
+
+fn to_document(active_params: &ActiveParams) -> Result<String> {
+    let front_matter = FrontMatter {
+        description: active_params.description.clone(),
+        priority: active_params.priority,
+        due: active_params.due,
+        project: active_params.project.clone(),
+        tags: active_params.tags.clone(),
+    };
+    let front_matter =
+        toml::to_string(&front_matter).map_err(|e| SigoError::EditorFormatErr(e.to_string()))?;
+    // comments stay inside the front matter, every line after it is an annotation
+    let mut document = format!(
+        "{0}\n# sigo {1} ({2})\n# annotations follow the closing {0}, one per line\n{3}{0}\n",
+        FRONT_MATTER_DELIMITER, active_params.id, active_params.uuid, front_matter
+    );
+    for annotation in active_params.annotations.iter() {
+        document.push_str(&annotation.text);
+        document.push('\n');
+    }
+    Ok(document)
+}
+
+fn from_document(
+    active_params: &ActiveParams,
+    document: &str,
+) -> std::result::Result<ActiveParams, String> {
+    let lines = document.lines().collect::<Vec<&str>>();
+    let delimiters = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.trim() == FRONT_MATTER_DELIMITER)
+        .map(|(index, _)| index)
+        .take(2)
+        .collect::<Vec<usize>>();
+    let (start, end) = match delimiters[..] {
+        [start, end] => (start, end),
+        _ => {
+            return Err(format!(
+                "the front matter must be enclosed by '{}' lines",
+                FRONT_MATTER_DELIMITER
+            ))
+        }
+    };
+    if lines[..start].iter().any(|line| !line.trim().is_empty()) {
+        return Err(format!(
+            "nothing may precede the first '{}' line",
+            FRONT_MATTER_DELIMITER
+        ));
+    }
+    let front_matter = lines[start + 1..end].join("\n");
+    let front_matter =
+        toml::from_str::<FrontMatter>(&front_matter).map_err(|e| e.message().to_string())?;
+    let annotations = lines[end + 1..]
+        .iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<String>>();
+
+    if let Some(project) = &front_matter.project {
+        parse_project(project)?;
+    }
+    for tag in front_matter.tags.iter() {
+        TagChange::from_str(&format!("+{}", tag))?;
+    }
+    let edited_params = active_params
+        .modify_priority(Some(to_update(front_matter.priority)))
+        .modify_due(Some(to_update(front_matter.due)))
+        .modify_project(Some(to_update(front_matter.project)))
+        .replace_tags(front_matter.tags)
+        .modify_description(Some(&front_matter.description), false)
+        .map_err(|e| e.to_string())?
+        .replace_annotations(annotations);
+    Ok(edited_params)
+}
+
+fn to_update<T>(value: Option<T>) -> Update<T> {
+    match value {
+        Some(value) => Update::Set(value),
+        None => Update::Clear,
+    }
+}
+
+// errors are only ever written above the front matter
+fn strip_errors(document: &str) -> String {
+    document
+        .lines()
+        .skip_while(|line| line.starts_with(ERROR_PREFIX))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+fn open_editor(path: &Path) -> Result<()> {
+    // an empty variable counts as unset
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = process::Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .map_err(|e| SigoError::EditorErr(editor.clone(), e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(SigoError::EditorExitErr(editor, status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn params() -> ActiveParams {
+        serde_json::from_value(json!({
+            "id": 2,
+            "uuid": "67e55044-10b1-426f-9247-bb680e5fe0c8",
+            "priority": "H",
+            "description": "write the report",
+            "annotations": [
+                {"entry": null, "text": "# not a comment"},
+                {"entry": null, "text": "ask about the numbers"}
+            ],
+            "tags": ["review", "work"],
+            "project": "office.q3",
+            "due": "2024-06-28",
+            "modified": "2024-06-01T09:00:00+09:00"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn unchanged_document_gives_the_same_sigo() {
+        let params = params();
+        let edited = from_document(&params, &to_document(&params).unwrap()).unwrap();
+        assert_eq!(edited, params);
+        assert_eq!(edited.due, params.due);
+        assert_eq!(edited.annotations, params.annotations);
+        assert_eq!(edited.modified, params.modified);
+    }
+
+    #[test]
+    fn misspelled_field_is_an_error() {
+        let params = params();
+        let document = to_document(&params)
+            .unwrap()
+            .replace("priority = ", "prority = ");
+        assert!(from_document(&params, &document).is_err());
+    }
+}
@@ -1,7 +1,13 @@
-use std::{error::Error, fmt, path::PathBuf};
+use std::{error::Error, fmt, path::PathBuf, process::ExitStatus, time::Duration};
 
 pub type Result<T> = std::result::Result<T, SigoError>;
 
+/// Exit codes of sigo, 2 is left to clap for malformed command lines.
+pub const EXIT_INVALID_USAGE: u8 = 3;
+pub const EXIT_NOT_FOUND: u8 = 4;
+pub const EXIT_STORAGE: u8 = 5;
+pub const EXIT_CORRUPTION: u8 = 6;
+
 #[derive(Debug)]
 pub enum SigoError {
     FileCreateErr(PathBuf, std::io::Error),
@@ -11,6 +17,28 @@ pub enum SigoError {
     ParseStrToTasksErr(PathBuf, serde_json::Error),
     ParseTasksToStrErr(serde_json::Error),
     TaskNotFound(u32),
+    TaskAlreadyCompleted(u32),
+    TaskNotCompleted(u32),
+    UuidNotFound(String),
+    AnnotationNotFound(u32, usize),
+    EmptyDescription(u32),
+    EditAborted(u32),
+    EditorFormatErr(String),
+    EditorErr(String, std::io::Error),
+    EditorExitErr(String, ExitStatus),
+    AmbiguousUuid(String, usize),
+    ParseJournalErr(PathBuf, serde_json::Error),
+    NothingToUndo,
+    UndoConflict(PathBuf),
+    FileLockErr(PathBuf, std::io::Error),
+    LockTimeout(PathBuf, Duration),
+    ParseManifestErr(PathBuf, serde_json::Error),
+    StdioErr(std::io::Error),
+    InvalidFilter(String),
+    ParseHolidaysErr(PathBuf, usize, String),
+    EditConflict(u32),
+    AmbiguousId(u32, Vec<String>),
+    ParseConfigErr(PathBuf, String),
 }
 
 impl fmt::Display for SigoError {
@@ -35,10 +63,161 @@ impl fmt::Display for SigoError {
             }
             SigoError::ParseTasksToStrErr(err) => writeln!(f, "unbale to parse sigo {}", err),
             SigoError::TaskNotFound(id) => writeln!(f, "not found sigo {}", id),
+            SigoError::TaskAlreadyCompleted(id) => {
+                writeln!(f, "sigo {} is already completed, reopen it first", id)
+            }
+            SigoError::TaskNotCompleted(id) => writeln!(f, "sigo {} is not completed", id),
+            SigoError::UuidNotFound(prefix) => writeln!(f, "not found sigo with uuid {}", prefix),
+            SigoError::AnnotationNotFound(id, number) => {
+                writeln!(f, "not found annotation {} of sigo {}", number, id)
+            }
+            SigoError::EmptyDescription(id) => {
+                writeln!(f, "description of sigo {} cannot be empty", id)
+            }
+            SigoError::EditAborted(id) => {
+                writeln!(f, "edit of sigo {} aborted, file was empty", id)
+            }
+            SigoError::EditorFormatErr(err) => {
+                writeln!(f, "unable to format sigo for editor: {}", err)
+            }
+            SigoError::EditorErr(editor, ref err) => {
+                writeln!(f, "unable to run editor {:?}: {}", editor, err)
+            }
+            SigoError::EditorExitErr(editor, status) => {
+                writeln!(f, "editor {:?} exited with {}", editor, status)
+            }
+            SigoError::AmbiguousUuid(prefix, count) => writeln!(
+                f,
+                "uuid prefix {} matches {} sigos, use a longer prefix",
+                prefix, count
+            ),
+            SigoError::AmbiguousId(id, prefixes) => writeln!(
+                f,
+                "id {} matches {} completed sigos, use a uuid prefix instead: {}",
+                id,
+                prefixes.len(),
+                prefixes.join(", ")
+            ),
+            SigoError::ParseConfigErr(path, message) => {
+                writeln!(f, "unable to load config {:?}: {}", path, message)
+            }
+            SigoError::ParseJournalErr(path, ref err) => {
+                writeln!(f, "unable to parse journal {:?}: {}", path, err)
+            }
+            SigoError::NothingToUndo => writeln!(f, "nothing to undo"),
+            SigoError::UndoConflict(path) => {
+                writeln!(
+                    f,
+                    "unable to undo, file {:?} was changed outside sigo",
+                    path
+                )
+            }
+            SigoError::FileLockErr(path, ref err) => {
+                writeln!(f, "unable to lock file {:?}: {}", path, err)
+            }
+            SigoError::LockTimeout(path, timeout) => writeln!(
+                f,
+                "unable to lock file {:?} within {}s, another sigo may be running",
+                path,
+                timeout.as_secs()
+            ),
+            SigoError::ParseManifestErr(path, ref err) => {
+                writeln!(f, "unable to parse transaction {:?}: {}", path, err)
+            }
+            SigoError::StdioErr(ref err) => writeln!(f, "unable to use stdio: {}", err),
+            SigoError::InvalidFilter(err) => writeln!(f, "invalid filter: {}", err),
+            SigoError::ParseHolidaysErr(path, line, holiday) => writeln!(
+                f,
+                "unable to parse holidays {:?} at line {}: {:?} is not yyyy-mm-dd",
+                path, line, holiday
+            ),
+            SigoError::EditConflict(id) => writeln!(
+                f,
+                "sigo {} was changed while it was being edited, edit it again",
+                id
+            ),
         }
     }
 }
 
+impl SigoError {
+    /// Name of the error kept stable for scripts reading `--output json`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SigoError::FileCreateErr(..) => "file_create",
+            SigoError::FileReadErr(..) => "file_read",
+            SigoError::FileWriteErr(..) => "file_write",
+            SigoError::FileRenameErr(..) => "file_rename",
+            SigoError::ParseStrToTasksErr(..) => "parse_tasks",
+            SigoError::ParseTasksToStrErr(..) => "serialize_tasks",
+            SigoError::TaskNotFound(..) => "task_not_found",
+            SigoError::TaskAlreadyCompleted(..) => "task_already_completed",
+            SigoError::TaskNotCompleted(..) => "task_not_completed",
+            SigoError::UuidNotFound(..) => "uuid_not_found",
+            SigoError::AnnotationNotFound(..) => "annotation_not_found",
+            SigoError::EmptyDescription(..) => "empty_description",
+            SigoError::EditAborted(..) => "edit_aborted",
+            SigoError::EditorFormatErr(..) => "editor_format",
+            SigoError::EditorErr(..) => "editor",
+            SigoError::EditorExitErr(..) => "editor_exit",
+            SigoError::AmbiguousUuid(..) => "ambiguous_uuid",
+            SigoError::ParseJournalErr(..) => "parse_journal",
+            SigoError::NothingToUndo => "nothing_to_undo",
+            SigoError::UndoConflict(..) => "undo_conflict",
+            SigoError::FileLockErr(..) => "file_lock",
+            SigoError::LockTimeout(..) => "lock_timeout",
+            SigoError::ParseManifestErr(..) => "parse_manifest",
+            SigoError::StdioErr(..) => "stdio",
+            SigoError::InvalidFilter(..) => "invalid_filter",
+            SigoError::ParseHolidaysErr(..) => "parse_holidays",
+            SigoError::EditConflict(..) => "edit_conflict",
+            SigoError::AmbiguousId(..) => "ambiguous_id",
+            SigoError::ParseConfigErr(..) => "parse_config",
+        }
+    }
+
+    // grouped by what a script can do about the error
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            SigoError::TaskNotFound(..)
+            | SigoError::UuidNotFound(..)
+            | SigoError::AnnotationNotFound(..) => EXIT_NOT_FOUND,
+            SigoError::FileCreateErr(..)
+            | SigoError::FileReadErr(..)
+            | SigoError::FileWriteErr(..)
+            | SigoError::FileRenameErr(..)
+            | SigoError::FileLockErr(..)
+            | SigoError::LockTimeout(..)
+            | SigoError::StdioErr(..)
+            | SigoError::EditorErr(..)
+            | SigoError::EditorExitErr(..) => EXIT_STORAGE,
+            SigoError::ParseStrToTasksErr(..)
+            | SigoError::ParseTasksToStrErr(..)
+            | SigoError::ParseJournalErr(..)
+            | SigoError::ParseManifestErr(..)
+            | SigoError::UndoConflict(..)
+            | SigoError::ParseHolidaysErr(..)
+            | SigoError::ParseConfigErr(..)
+            | SigoError::EditorFormatErr(..) => EXIT_CORRUPTION,
+            SigoError::TaskAlreadyCompleted(..)
+            | SigoError::TaskNotCompleted(..)
+            | SigoError::EmptyDescription(..)
+            | SigoError::EditAborted(..)
+            | SigoError::EditConflict(..)
+            | SigoError::AmbiguousUuid(..)
+            | SigoError::AmbiguousId(..)
+            | SigoError::NothingToUndo
+            | SigoError::InvalidFilter(..) => EXIT_INVALID_USAGE,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "error": {"code": self.code(), "message": self.to_string().trim_end()}
+        })
+    }
+}
+
 impl Error for SigoError {}
 
 impl From<serde_json::Error> for SigoError {
@@ -1,17 +1,31 @@
-use std::{io::Write, path::PathBuf};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
 
-use chrono::NaiveDate;
+use chrono::Local;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    active_params::{ActiveParams, Modification, State},
     config::MyConfig,
     error::{Result, SigoError},
-    task::{CompletedTask, ReadyTask, WaitingTask},
-    utils, Priority,
+    task::{CompletedTask, DeletedTask, ReadyTask, WaitingTask},
+    utils,
 };
 
 pub trait Filable: Serialize + for<'a> Deserialize<'a> + Clone {
     fn get_filename() -> String;
+
+    // called with the index of each sigo read from the store file
+    fn at_position(self, _index: usize) -> Self {
+        self
+    }
 }
 
 impl Filable for ReadyTask {
@@ -30,119 +44,379 @@ impl Filable for CompletedTask {
     fn get_filename() -> String {
         "completed_tasks".to_string()
     }
+
+    // legacy summaries have neither an id nor a uuid, only their position tells them apart
+    fn at_position(mut self, index: usize) -> Self {
+        if self.active_params.uuid.is_nil() {
+            self.active_params.uuid = ActiveParams::legacy_uuid(
+                index as u32,
+                std::slice::from_ref(&self.active_params.description),
+            );
+        }
+        self
+    }
+}
+
+impl Filable for DeletedTask {
+    fn get_filename() -> String {
+        "deleted_tasks".to_string()
+    }
+}
+
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Take an exclusive advisory lock on the data directory.
+/// The lock is held until the returned file is dropped.
+pub fn lock_store(cfg: &MyConfig) -> Result<std::fs::File> {
+    let mut path = PathBuf::from(&cfg.data);
+    path.push("lock");
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .map_err(|e| SigoError::FileCreateErr(path.clone(), e))?;
+    let started = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(file),
+            Err(std::fs::TryLockError::WouldBlock) if started.elapsed() < LOCK_TIMEOUT => {
+                thread::sleep(LOCK_RETRY_INTERVAL)
+            }
+            Err(std::fs::TryLockError::WouldBlock) => {
+                return Err(SigoError::LockTimeout(path, LOCK_TIMEOUT))
+            }
+            Err(std::fs::TryLockError::Error(e)) => return Err(SigoError::FileLockErr(path, e)),
+        }
+    }
+}
+
+/// Pending changes to the store files of one sigo invocation.
+/// Each store file is read once, reads see the staged contents, and nothing reaches
+/// the disk until `commit`.
+pub struct Transaction<'a> {
+    cfg: &'a MyConfig,
+    loaded: RefCell<BTreeMap<String, Vec<serde_json::Value>>>,
+    staged: BTreeMap<String, Vec<serde_json::Value>>,
+    undone: Option<usize>,
+    session: String,
+}
+
+/// The staged contents at some point of a transaction, to go back to with `rollback`.
+pub struct Savepoint(BTreeMap<String, Vec<serde_json::Value>>);
+
+impl<'a> Transaction<'a> {
+    pub fn begin(cfg: &'a MyConfig) -> Self {
+        Self {
+            cfg,
+            loaded: RefCell::new(BTreeMap::new()),
+            staged: BTreeMap::new(),
+            undone: None,
+            session: new_session_id(),
+        }
+    }
+
+    fn path(&self, filename: &str) -> PathBuf {
+        let mut path = PathBuf::from(&self.cfg.data);
+        path.push(filename);
+        path
+    }
+
+    fn read_values(&self, filename: &str) -> Result<Vec<serde_json::Value>> {
+        if let Some(values) = self.staged.get(filename) {
+            return Ok(values.clone());
+        }
+        self.read_loaded_values(filename)
+    }
+
+    // the contents on disk when the transaction first read them
+    fn read_loaded_values(&self, filename: &str) -> Result<Vec<serde_json::Value>> {
+        if let Some(values) = self.loaded.borrow().get(filename) {
+            return Ok(values.clone());
+        }
+        let path = self.path(filename);
+        utils::create_file_if_not_exist(&path)?;
+        let values = read_values(&path)?;
+        self.loaded
+            .borrow_mut()
+            .insert(filename.to_string(), values.clone());
+        Ok(values)
+    }
+
+    fn stage_values(&mut self, filename: &str, values: Vec<serde_json::Value>) {
+        self.staged.insert(filename.to_string(), values);
+    }
+
+    pub fn savepoint(&self) -> Savepoint {
+        Savepoint(self.staged.clone())
+    }
+
+    pub fn rollback(&mut self, savepoint: Savepoint) {
+        self.staged = savepoint.0;
+    }
+
+    /// Write every staged file at once.
+    /// The new contents are first written next to the store files, then a manifest
+    /// listing them is renamed into place, so `recover` can finish an interrupted commit.
+    pub fn commit(self) -> Result<()> {
+        if self.staged.is_empty() && self.undone.is_none() {
+            return Ok(());
+        }
+
+        let mut manifest = Manifest {
+            session: self.session.clone(),
+            files: Vec::new(),
+            journal: Vec::new(),
+            undone: self.undone,
+        };
+        for (filename, after) in self.staged.iter() {
+            let path = self.path(filename);
+            if self.undone.is_none() {
+                let before = self.read_loaded_values(filename)?;
+                manifest
+                    .journal
+                    .extend(journal_entry(&self.session, filename, &before, after));
+            }
+            let tmp_path = tmp_path(&path);
+            write_file(&tmp_path, &serde_json::to_string(after)?)?;
+            manifest.files.push((tmp_path, path));
+        }
+
+        let path = manifest_path(self.cfg);
+        let tmp_path = tmp_path(&path);
+        write_file(&tmp_path, &serde_json::to_string(&manifest)?)?;
+        std::fs::rename(&tmp_path, &path)
+            .map_err(|e| SigoError::FileRenameErr(tmp_path.clone(), path.clone(), e))?;
+        apply_manifest(self.cfg, manifest)
+    }
 }
 
-pub fn read_tasks<T>(cfg: &MyConfig) -> Result<Vec<T>>
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    session: String,
+    files: Vec<(PathBuf, PathBuf)>,
+    journal: Vec<JournalEntry>,
+    undone: Option<usize>,
+}
+
+fn manifest_path(cfg: &MyConfig) -> PathBuf {
+    let mut path = PathBuf::from(&cfg.data);
+    path.push("transaction");
+    path
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    path.with_extension(format!("sigo-tmp-{}", std::process::id()))
+}
+
+fn apply_manifest(cfg: &MyConfig, manifest: Manifest) -> Result<()> {
+    for (tmp_path, path) in manifest.files.iter() {
+        if tmp_path.is_file() {
+            std::fs::rename(tmp_path, path)
+                .map_err(|e| SigoError::FileRenameErr(tmp_path.clone(), path.clone(), e))?;
+        }
+    }
+
+    match manifest.undone {
+        Some(len) => {
+            let mut entries = read_journal(cfg)?;
+            entries.truncate(len);
+            write_journal(cfg, &entries)?;
+        }
+        None => append_journal(cfg, &manifest.journal)?,
+    }
+
+    let path = manifest_path(cfg);
+    std::fs::remove_file(&path).map_err(|e| SigoError::FileWriteErr(path.clone(), e))
+}
+
+/// Finish a commit interrupted by a crash, and throw away the files of commits
+/// that never got as far as their manifest.
+pub fn recover(cfg: &MyConfig) -> Result<()> {
+    let path = manifest_path(cfg);
+    if path.is_file() {
+        let manifest =
+            std::fs::read_to_string(&path).map_err(|e| SigoError::FileReadErr(path.clone(), e))?;
+        let mut manifest = serde_json::from_str::<Manifest>(&manifest)
+            .map_err(|e| SigoError::ParseManifestErr(path.clone(), e))?;
+        // the crash may have happened after the journal was already written
+        if read_journal(cfg)?
+            .iter()
+            .any(|e| e.session == manifest.session)
+        {
+            manifest.journal.clear();
+        }
+        apply_manifest(cfg, manifest)?;
+    }
+
+    let dir = PathBuf::from(&cfg.data);
+    let entries = std::fs::read_dir(&dir).map_err(|e| SigoError::FileReadErr(dir.clone(), e))?;
+    for entry in entries.flatten() {
+        if entry.file_name().to_string_lossy().contains(".sigo-tmp-") {
+            std::fs::remove_file(entry.path())
+                .map_err(|e| SigoError::FileWriteErr(entry.path(), e))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn read_tasks<T>(tx: &Transaction) -> Result<Vec<T>>
 where
     T: Filable,
 {
-    let mut path = PathBuf::from(&cfg.data);
-    path.push(T::get_filename());
-    utils::create_file_if_not_exist(&path)?;
-    let tasks = std::fs::read_to_string(path.clone())
-        .map_err(|e| SigoError::FileReadErr(path.clone(), e))?;
-    let tasks = serde_json::from_str::<Vec<T>>(&tasks)
-        .map_err(|e| SigoError::ParseStrToTasksErr(path.clone(), e))?;
-    Ok(tasks)
+    tx.read_values(&T::get_filename())?
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| serde_json::from_value::<T>(value).map(|t| t.at_position(index)))
+        .collect::<serde_json::Result<Vec<T>>>()
+        .map_err(|e| SigoError::ParseStrToTasksErr(tx.path(&T::get_filename()), e))
 }
 
-pub fn write_tasks<T>(cfg: &MyConfig, tasks: Vec<T>) -> Result<()>
+pub fn write_tasks<T>(tx: &mut Transaction, tasks: Vec<T>) -> Result<()>
 where
     T: Filable,
 {
-    let mut path = PathBuf::from(&cfg.data);
-    path.push(T::get_filename());
-    utils::create_file_if_not_exist(&path)?;
-    let tmp_path = path.with_extension(format!("sigo-tmp-{}", std::process::id()));
-    let mut file = std::fs::File::create(&tmp_path)
-        .map_err(|e| SigoError::FileCreateErr(tmp_path.clone(), e))?;
-    let tasks = serde_json::to_string(&tasks)?;
-    std::io::BufWriter::with_capacity(tasks.len(), &file)
-        .write_all(tasks.as_bytes())
-        .map_err(|e| SigoError::FileWriteErr(tmp_path.clone(), e))?;
-    file.flush()
-        .map_err(|e| SigoError::FileWriteErr(tmp_path.clone(), e))?;
-    std::fs::rename(&tmp_path, &path)
-        .map_err(|e| SigoError::FileRenameErr(tmp_path.clone(), path.clone(), e))?;
+    let values = tasks
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<serde_json::Result<Vec<serde_json::Value>>>()?;
+    tx.stage_values(&T::get_filename(), values);
     Ok(())
 }
 
-pub fn add_task<T>(cfg: &MyConfig, task: T) -> Result<T>
+fn read_values(path: &Path) -> Result<Vec<serde_json::Value>> {
+    let values =
+        std::fs::read_to_string(path).map_err(|e| SigoError::FileReadErr(path.to_path_buf(), e))?;
+    let values = serde_json::from_str::<Vec<serde_json::Value>>(&values)
+        .map_err(|e| SigoError::ParseStrToTasksErr(path.to_path_buf(), e))?;
+    Ok(values)
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<()> {
+    let file =
+        std::fs::File::create(path).map_err(|e| SigoError::FileCreateErr(path.to_path_buf(), e))?;
+    let mut writer = std::io::BufWriter::with_capacity(contents.len(), &file);
+    writer
+        .write_all(contents.as_bytes())
+        .map_err(|e| SigoError::FileWriteErr(path.to_path_buf(), e))?;
+    writer
+        .flush()
+        .map_err(|e| SigoError::FileWriteErr(path.to_path_buf(), e))?;
+    file.sync_all()
+        .map_err(|e| SigoError::FileWriteErr(path.to_path_buf(), e))?;
+    Ok(())
+}
+
+pub fn add_task<T>(tx: &mut Transaction, task: T) -> Result<T>
 where
     T: Filable,
 {
-    let mut tasks = read_tasks::<T>(cfg)?;
+    let mut tasks = read_tasks::<T>(tx)?;
     tasks.push(task.clone());
-    write_tasks::<T>(cfg, tasks)?;
+    write_tasks::<T>(tx, tasks)?;
     Ok(task)
 }
 
 pub trait ActiveFilable: Filable {
     fn get_id(&self) -> u32;
-    fn get_description(&self) -> Vec<String>;
-    fn add_annotation(&self, text: &str) -> Self;
-    fn modify_params(&self, priority: Option<Priority>, due: Option<NaiveDate>) -> Self;
+    fn get_active_params(&self) -> ActiveParams;
+    fn with_active_params(&self, active_params: ActiveParams) -> Self;
 
-    fn complete(&self, cfg: &MyConfig) -> Result<CompletedTask> {
-        delete_by_id::<Self>(cfg, self.get_id())?;
+    fn add_annotation(&self, text: &str) -> Self {
+        self.with_active_params(self.get_active_params().annotate_description(text))
+    }
+
+    fn modify_params(&self, modification: &Modification) -> Result<Self> {
+        let active_params = self
+            .get_active_params()
+            .modify_priority(modification.priority)
+            .modify_due(modification.due)
+            .modify_tags(&modification.tags)
+            .modify_project(modification.project.clone())
+            .modify_description(
+                modification.description.as_deref(),
+                modification.clear_annotations,
+            )?;
+        Ok(self.with_active_params(active_params))
+    }
+
+    fn complete(&self, tx: &mut Transaction) -> Result<CompletedTask> {
+        delete_by_id::<Self>(tx, self.get_id())?;
         let completed_task = CompletedTask {
-            summary: self.get_description().concat(),
+            active_params: self.get_active_params().change_state(State::Completed),
+            completed_at: Some(Local::now()),
         };
-        add_task::<CompletedTask>(cfg, completed_task.clone())?;
+        add_task::<CompletedTask>(tx, completed_task.clone())?;
         Ok(completed_task)
     }
 
-    fn annotate(&self, cfg: &MyConfig, text: &str) -> Result<Self> {
-        let before_tasks = read_tasks::<Self>(cfg)?;
-        let mut after_tasks = before_tasks
-            .into_iter()
-            .filter(|t| t.get_id() != self.get_id())
-            .collect::<Vec<Self>>();
-        let annotated_task = self.add_annotation(text);
-        after_tasks.push(annotated_task.clone());
-        write_tasks::<Self>(cfg, after_tasks)?;
-        Ok(annotated_task)
-    }
-
-    fn modify(
-        &self,
-        cfg: &MyConfig,
-        priority: Option<Priority>,
-        due: Option<NaiveDate>,
-    ) -> Result<Self> {
-        let before_tasks = read_tasks::<Self>(cfg)?;
-        let mut after_tasks = before_tasks
+    fn delete(&self, tx: &mut Transaction) -> Result<DeletedTask> {
+        delete_by_id::<Self>(tx, self.get_id())?;
+        let deleted_task = DeletedTask {
+            active_params: self.get_active_params(),
+            deleted_at: Local::now(),
+        };
+        add_task::<DeletedTask>(tx, deleted_task.clone())?;
+        Ok(deleted_task)
+    }
+
+    // replace this sigo in its store file, keeping its position
+    fn update(&self, tx: &mut Transaction, updated_task: Self) -> Result<Self> {
+        let tasks = read_tasks::<Self>(tx)?
             .into_iter()
-            .filter(|t| t.get_id() != self.get_id())
+            .map(|t| {
+                if t.get_id() == self.get_id() {
+                    updated_task.clone()
+                } else {
+                    t
+                }
+            })
             .collect::<Vec<Self>>();
-        let modified_task = self.modify_params(priority, due);
-        after_tasks.push(modified_task.clone());
-        write_tasks::<Self>(cfg, after_tasks)?;
-        Ok(modified_task)
+        write_tasks::<Self>(tx, tasks)?;
+        Ok(updated_task)
+    }
+
+    fn annotate(&self, tx: &mut Transaction, text: &str) -> Result<Self> {
+        self.update(tx, self.add_annotation(text))
+    }
+
+    fn edit_annotation(&self, tx: &mut Transaction, number: usize, text: &str) -> Result<Self> {
+        let active_params = self.get_active_params().edit_annotation(number, text)?;
+        self.update(tx, self.with_active_params(active_params))
+    }
+
+    fn denotate(&self, tx: &mut Transaction, number: usize) -> Result<Self> {
+        let active_params = self.get_active_params().remove_annotation(number)?;
+        self.update(tx, self.with_active_params(active_params))
+    }
+
+    fn modify(&self, tx: &mut Transaction, modification: &Modification) -> Result<Self> {
+        self.update(tx, self.modify_params(modification)?)
     }
 }
 
-pub fn get_by_id<T>(cfg: &MyConfig, id: u32) -> Result<T>
+pub fn get_by_id<T>(tx: &Transaction, id: u32) -> Result<T>
 where
     T: ActiveFilable + for<'a> Deserialize<'a>,
 {
-    let tasks = read_tasks::<T>(cfg)?;
+    let tasks = read_tasks::<T>(tx)?;
     tasks
         .into_iter()
         .find(|t| t.get_id() == id)
         .ok_or(SigoError::TaskNotFound(id))
 }
 
-pub fn delete_by_id<T>(cfg: &MyConfig, id: u32) -> Result<()>
+pub fn delete_by_id<T>(tx: &mut Transaction, id: u32) -> Result<()>
 where
     T: ActiveFilable + Serialize + for<'a> Deserialize<'a>,
 {
-    let tasks = read_tasks::<T>(cfg)?;
+    let tasks = read_tasks::<T>(tx)?;
     let updated_tasks = tasks
         .into_iter()
         .filter(|t| t.get_id() != id)
         .collect::<Vec<T>>();
-    write_tasks::<T>(cfg, updated_tasks)?;
+    write_tasks::<T>(tx, updated_tasks)?;
     Ok(())
 }
 
@@ -151,20 +425,12 @@ impl ActiveFilable for ReadyTask {
         self.active_params.id
     }
 
-    fn get_description(&self) -> Vec<String> {
-        self.active_params.description.clone()
-    }
-
-    fn add_annotation(&self, text: &str) -> Self {
-        ReadyTask {
-            active_params: self.active_params.annotate_description(text),
-        }
+    fn get_active_params(&self) -> ActiveParams {
+        self.active_params.clone()
     }
 
-    fn modify_params(&self, priority: Option<Priority>, due: Option<NaiveDate>) -> Self {
-        ReadyTask {
-            active_params: self.active_params.modify_priority(priority).modify_due(due),
-        }
+    fn with_active_params(&self, active_params: ActiveParams) -> Self {
+        ReadyTask { active_params }
     }
 }
 
@@ -173,19 +439,343 @@ impl ActiveFilable for WaitingTask {
         self.active_params.id
     }
 
-    fn get_description(&self) -> Vec<String> {
-        self.active_params.description.clone()
+    fn get_active_params(&self) -> ActiveParams {
+        self.active_params.clone()
     }
 
-    fn add_annotation(&self, text: &str) -> Self {
-        WaitingTask {
-            active_params: self.active_params.annotate_description(text),
+    fn with_active_params(&self, active_params: ActiveParams) -> Self {
+        WaitingTask { active_params }
+    }
+}
+
+/// One change to a store file, recorded so that `sigo undo` can revert it.
+/// `removed` keeps the original index of each value so the file order is restored too.
+#[derive(Serialize, Deserialize)]
+struct JournalEntry {
+    session: String,
+    command: String,
+    filename: String,
+    removed: Vec<(usize, serde_json::Value)>,
+    added: Vec<serde_json::Value>,
+}
+
+fn journal_path(cfg: &MyConfig) -> PathBuf {
+    let mut path = PathBuf::from(&cfg.data);
+    path.push("journal");
+    path
+}
+
+// All changes committed by one transaction share a session and are undone together
+fn new_session_id() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    format!(
+        "{}-{}-{}",
+        Local::now().format("%Y%m%d%H%M%S%f"),
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+fn journal_entry(
+    session: &str,
+    filename: &str,
+    before: &[serde_json::Value],
+    after: &[serde_json::Value],
+) -> Option<JournalEntry> {
+    let mut matched = vec![false; after.len()];
+    let mut removed = Vec::new();
+    for (index, value) in before.iter().enumerate() {
+        match (0..after.len()).find(|&i| !matched[i] && after[i] == *value) {
+            Some(i) => matched[i] = true,
+            None => removed.push((index, value.clone())),
         }
     }
+    let added = after
+        .iter()
+        .zip(matched)
+        .filter(|(_, m)| !m)
+        .map(|(v, _)| v.clone())
+        .collect::<Vec<serde_json::Value>>();
+    if removed.is_empty() && added.is_empty() {
+        return None;
+    }
+
+    Some(JournalEntry {
+        session: session.to_string(),
+        command: std::env::args().skip(1).collect::<Vec<String>>().join(" "),
+        filename: filename.to_string(),
+        removed,
+        added,
+    })
+}
+
+fn read_journal(cfg: &MyConfig) -> Result<Vec<JournalEntry>> {
+    let path = journal_path(cfg);
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    let file = std::fs::File::open(&path).map_err(|e| SigoError::FileReadErr(path.clone(), e))?;
+    std::io::BufReader::new(file)
+        .lines()
+        .map(|line| {
+            let line = line.map_err(|e| SigoError::FileReadErr(path.clone(), e))?;
+            serde_json::from_str::<JournalEntry>(&line)
+                .map_err(|e| SigoError::ParseJournalErr(path.clone(), e))
+        })
+        .collect()
+}
+
+fn append_journal(cfg: &MyConfig, entries: &[JournalEntry]) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let path = journal_path(cfg);
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| SigoError::FileCreateErr(path.clone(), e))?;
+    for entry in entries.iter() {
+        writeln!(file, "{}", serde_json::to_string(entry)?)
+            .map_err(|e| SigoError::FileWriteErr(path.clone(), e))?;
+    }
+    Ok(())
+}
+
+fn write_journal(cfg: &MyConfig, entries: &[JournalEntry]) -> Result<()> {
+    let path = journal_path(cfg);
+    let journal = entries
+        .iter()
+        .map(|e| serde_json::to_string(e).map(|s| s + "\n"))
+        .collect::<serde_json::Result<String>>()?;
+    let tmp_path = tmp_path(&path);
+    write_file(&tmp_path, &journal)?;
+    std::fs::rename(&tmp_path, &path)
+        .map_err(|e| SigoError::FileRenameErr(tmp_path.clone(), path.clone(), e))
+}
+
+/// Stage the revert of every change made by the latest journaled sigo invocation
+/// and return the command line of that invocation.
+pub fn undo(tx: &mut Transaction) -> Result<String> {
+    let entries = read_journal(tx.cfg)?;
+    let session = match entries.last() {
+        Some(entry) => entry.session.clone(),
+        None => return Err(SigoError::NothingToUndo),
+    };
+    let split_at = entries
+        .iter()
+        .rposition(|e| e.session != session)
+        .map_or(0, |i| i + 1);
 
-    fn modify_params(&self, priority: Option<Priority>, due: Option<NaiveDate>) -> Self {
-        WaitingTask {
-            active_params: self.active_params.modify_priority(priority).modify_due(due),
+    for entry in entries[split_at..].iter().rev() {
+        let mut values = tx.read_values(&entry.filename)?;
+        for value in entry.added.iter() {
+            let index = values
+                .iter()
+                .rposition(|v| v == value)
+                .ok_or(SigoError::UndoConflict(tx.path(&entry.filename)))?;
+            values.remove(index);
+        }
+        for (index, value) in entry.removed.iter() {
+            values.insert((*index).min(values.len()), value.clone());
         }
+        tx.stage_values(&entry.filename, values);
+    }
+    tx.undone = Some(split_at);
+
+    Ok(entries[split_at].command.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use serde_json::json;
+
+    use super::*;
+
+    const STORE_FILES: [&str; 3] = ["ready_tasks", "waiting_tasks", "completed_tasks"];
+
+    fn test_config(name: &str) -> MyConfig {
+        let dir = std::env::temp_dir().join(format!("sigo-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        MyConfig {
+            data: dir.into_os_string().into_string().unwrap(),
+            ..MyConfig::default()
+        }
+    }
+
+    fn entry(session: &str) -> JournalEntry {
+        JournalEntry {
+            session: session.to_string(),
+            command: "done 1".to_string(),
+            filename: "ready_tasks".to_string(),
+            removed: vec![(0, json!({"id": 1}))],
+            added: Vec::new(),
+        }
+    }
+
+    fn read_store(cfg: &MyConfig) -> Vec<Vec<serde_json::Value>> {
+        let tx = Transaction::begin(cfg);
+        STORE_FILES
+            .iter()
+            .map(|filename| tx.read_values(filename).unwrap())
+            .collect()
+    }
+
+    fn add(cfg: &MyConfig, description: &str) {
+        let mut tx = Transaction::begin(cfg);
+        let task = ReadyTask::new(&tx, description, None, None, BTreeSet::new(), None).unwrap();
+        add_task(&mut tx, task).unwrap();
+        tx.commit().unwrap();
+    }
+
+    fn wait(cfg: &MyConfig, id: u32) {
+        let mut tx = Transaction::begin(cfg);
+        let task = get_by_id::<ReadyTask>(&tx, id).unwrap();
+        task.wait(&mut tx, &Some("ask first".to_string())).unwrap();
+        tx.commit().unwrap();
+    }
+
+    fn undo_once(cfg: &MyConfig) -> Result<String> {
+        let mut tx = Transaction::begin(cfg);
+        let command = undo(&mut tx)?;
+        tx.commit()?;
+        Ok(command)
+    }
+
+    // a commit that crashed after renaming its manifest into place
+    fn write_manifest(cfg: &MyConfig, session: &str) -> PathBuf {
+        let path = PathBuf::from(&cfg.data).join("ready_tasks");
+        let tmp_path = tmp_path(&path);
+        write_file(&tmp_path, "[]").unwrap();
+        let manifest = Manifest {
+            session: session.to_string(),
+            files: vec![(tmp_path, path.clone())],
+            journal: vec![entry(session)],
+            undone: None,
+        };
+        write_file(
+            &manifest_path(cfg),
+            &serde_json::to_string(&manifest).unwrap(),
+        )
+        .unwrap();
+        path
+    }
+
+    #[test]
+    fn journal_entry_records_removed_indexes_and_added_values() {
+        let before = [json!(1), json!(2), json!(3), json!(2)];
+        let after = [json!(1), json!(3), json!(2), json!(4)];
+        let entry = journal_entry("s1", "ready_tasks", &before, &after).unwrap();
+        assert_eq!(entry.removed, vec![(3, json!(2))]);
+        assert_eq!(entry.added, vec![json!(4)]);
+        assert!(journal_entry("s1", "ready_tasks", &before, &before).is_none());
+    }
+
+    #[test]
+    fn recover_finishes_an_interrupted_commit() {
+        let cfg = test_config("recover");
+        let path = write_manifest(&cfg, "s1");
+        write_file(&path, r#"[{"id": 1}]"#).unwrap();
+
+        recover(&cfg).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[]");
+        assert!(!manifest_path(&cfg).exists());
+        assert!(!tmp_path(&path).exists());
+        let journal = read_journal(&cfg).unwrap();
+        assert_eq!(journal.len(), 1);
+        assert_eq!(journal[0].session, "s1");
+        std::fs::remove_dir_all(&cfg.data).unwrap();
+    }
+
+    #[test]
+    fn recover_does_not_journal_a_session_twice() {
+        let cfg = test_config("replay");
+        append_journal(&cfg, &[entry("s0"), entry("s1")]).unwrap();
+        let path = write_manifest(&cfg, "s1");
+
+        recover(&cfg).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[]");
+        let sessions = read_journal(&cfg)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.session)
+            .collect::<Vec<String>>();
+        assert_eq!(sessions, vec!["s0", "s1"]);
+        std::fs::remove_dir_all(&cfg.data).unwrap();
+    }
+
+    #[test]
+    fn recover_removes_files_of_commits_without_a_manifest() {
+        let cfg = test_config("leftover");
+        let path = PathBuf::from(&cfg.data).join("ready_tasks");
+        write_file(&path, "[]").unwrap();
+        write_file(&tmp_path(&path), r#"[{"id": 1}]"#).unwrap();
+
+        recover(&cfg).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[]");
+        assert!(!tmp_path(&path).exists());
+        std::fs::remove_dir_all(&cfg.data).unwrap();
+    }
+
+    #[test]
+    fn undo_reverts_one_transaction_at_a_time() {
+        let cfg = test_config("undo");
+        add(&cfg, "a");
+        add(&cfg, "b");
+        let original = read_store(&cfg);
+        wait(&cfg, 1);
+        let waited = read_store(&cfg);
+        let mut tx = Transaction::begin(&cfg);
+        get_by_id::<ReadyTask>(&tx, 2)
+            .unwrap()
+            .complete(&mut tx)
+            .unwrap();
+        tx.commit().unwrap();
+
+        undo_once(&cfg).unwrap();
+        assert_eq!(read_store(&cfg), waited);
+        undo_once(&cfg).unwrap();
+        assert_eq!(read_store(&cfg), original);
+        std::fs::remove_dir_all(&cfg.data).unwrap();
+    }
+
+    #[test]
+    fn undo_refuses_files_changed_outside_sigo() {
+        let cfg = test_config("undo-conflict");
+        add(&cfg, "a");
+        wait(&cfg, 1);
+        write_file(&PathBuf::from(&cfg.data).join("waiting_tasks"), "[]").unwrap();
+
+        assert!(matches!(undo_once(&cfg), Err(SigoError::UndoConflict(..))));
+        std::fs::remove_dir_all(&cfg.data).unwrap();
+    }
+
+    #[test]
+    fn legacy_summaries_get_a_stable_uuid_per_position() {
+        let cfg = test_config("legacy");
+        write_file(
+            &PathBuf::from(&cfg.data).join("completed_tasks"),
+            r#"[{"summary": "report"}, {"summary": "report"}]"#,
+        )
+        .unwrap();
+
+        let uuids = |cfg: &MyConfig| {
+            read_tasks::<CompletedTask>(&Transaction::begin(cfg))
+                .unwrap()
+                .into_iter()
+                .map(|t| t.active_params.uuid)
+                .collect::<Vec<uuid::Uuid>>()
+        };
+        let first = uuids(&cfg);
+        assert_ne!(first[0], first[1]);
+        assert!(!first[0].is_nil());
+        assert_eq!(uuids(&cfg), first);
+        std::fs::remove_dir_all(&cfg.data).unwrap();
     }
 }
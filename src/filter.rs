@@ -0,0 +1,342 @@
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use regex::Regex;
+
+use crate::active_params::{parse_project, ActiveParams, TagChange};
+use crate::{date, Priority};
+
+const FILTER_FORMS: &str = "filters are +tag, -tag, project:<name>, priority:<H/M/L/none>, \
+due:<date/none>, due.before:<date>, due.after:<date> or /regex/, \
+joined with and, or, not and parentheses";
+
+/// A condition on sigos, like `priority:H and (+review or /deploy/)`.
+/// Terms written next to each other without `and` or `or` must all match.
+#[derive(Clone, Debug)]
+pub enum Filter {
+    All,
+    HasTag(String),
+    LacksTag(String),
+    Project(String),
+    Priority(Option<Priority>),
+    Due(Option<NaiveDate>),
+    DueBefore(NaiveDate),
+    DueAfter(NaiveDate),
+    Text(Regex),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        if tokens.is_empty() {
+            return Ok(Filter::All);
+        }
+        let mut parser = Parser { tokens, pos: 0 };
+        let filter = parser.parse_or()?;
+        match parser.peek() {
+            Some(token) => Err(format!("unexpected '{}' in the filter", token)),
+            None => Ok(filter),
+        }
+    }
+}
+
+impl Filter {
+    // the words of a filter may come as separate command line arguments
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        args.join(" ").parse()
+    }
+
+    // true when the filter only rules sigos out, like -tag or not project:x,
+    // so it matches nearly every sigo
+    pub fn only_excludes(&self) -> bool {
+        match self {
+            Filter::All | Filter::LacksTag(_) | Filter::Not(_) => true,
+            Filter::And(left, right) => left.only_excludes() && right.only_excludes(),
+            Filter::Or(left, right) => left.only_excludes() || right.only_excludes(),
+            _ => false,
+        }
+    }
+
+    pub fn matches(&self, params: &ActiveParams) -> bool {
+        match self {
+            Filter::All => true,
+            Filter::HasTag(tag) => params.tags.contains(tag),
+            Filter::LacksTag(tag) => !params.tags.contains(tag),
+            Filter::Project(project) => params.is_in_project(project),
+            Filter::Priority(priority) => params.priority == *priority,
+            Filter::Due(due) => params.due == *due,
+            Filter::DueBefore(date) => params.due.is_some_and(|due| due < *date),
+            Filter::DueAfter(date) => params.due.is_some_and(|due| due > *date),
+            Filter::Text(regex) => {
+                regex.is_match(&params.description)
+                    || params.annotations.iter().any(|a| regex.is_match(&a.text))
+            }
+            Filter::Not(filter) => !filter.matches(params),
+            Filter::And(left, right) => left.matches(params) && right.matches(params),
+            Filter::Or(left, right) => left.matches(params) || right.matches(params),
+        }
+    }
+}
+
+// split into words, parentheses and /regex/ which may contain spaces
+fn tokenize(s: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' || c == ')' {
+            tokens.push(c.to_string());
+            chars.next();
+        } else if c == '/' {
+            let mut token = String::new();
+            token.push(c);
+            chars.next();
+            let mut closed = false;
+            while let Some(c) = chars.next() {
+                token.push(c);
+                if c == '\\' {
+                    if let Some(escaped) = chars.next() {
+                        token.push(escaped);
+                    }
+                } else if c == '/' {
+                    closed = true;
+                    break;
+                }
+            }
+            if !closed {
+                return Err(format!("'{}' is missing the closing '/'", token));
+            }
+            tokens.push(token);
+        } else {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+// not binds tighter than and, and binds tighter than or
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn eat(&mut self, keyword: &str) -> bool {
+        if self.peek().is_some_and(|t| t.eq_ignore_ascii_case(keyword)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.parse_and()?;
+        while self.eat("or") {
+            let right = self.parse_and()?;
+            filter = Filter::Or(Box::new(filter), Box::new(right));
+        }
+        Ok(filter)
+    }
+
+    fn parse_and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.parse_not()?;
+        loop {
+            let explicit = self.eat("and");
+            let implicit = self
+                .peek()
+                .is_some_and(|t| t != ")" && !t.eq_ignore_ascii_case("or"));
+            if !explicit && !implicit {
+                break;
+            }
+            let right = self.parse_not()?;
+            filter = Filter::And(Box::new(filter), Box::new(right));
+        }
+        Ok(filter)
+    }
+
+    fn parse_not(&mut self) -> Result<Filter, String> {
+        if self.eat("not") {
+            Ok(Filter::Not(Box::new(self.parse_not()?)))
+        } else {
+            self.parse_primary()
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Filter, String> {
+        let token = match self.peek() {
+            Some(token) => token.to_string(),
+            None => return Err("the filter ends unexpectedly".to_string()),
+        };
+        self.pos += 1;
+        match token.to_lowercase().as_str() {
+            "(" => {
+                let filter = self.parse_or()?;
+                if self.eat(")") {
+                    Ok(filter)
+                } else {
+                    Err("missing ')' in the filter".to_string())
+                }
+            }
+            ")" | "and" | "or" => Err(format!("unexpected '{}' in the filter", token)),
+            _ => parse_term(&token),
+        }
+    }
+}
+
+fn parse_term(token: &str) -> Result<Filter, String> {
+    if let Some(pattern) = token
+        .strip_prefix('/')
+        .and_then(|rest| rest.strip_suffix('/'))
+    {
+        return Regex::new(pattern)
+            .map(Filter::Text)
+            .map_err(|e| format!("'{}' is not a valid regex: {}", token, e));
+    }
+    if let Some((attribute, value)) = token.split_once(':') {
+        return match attribute {
+            "project" => parse_project(value).map(Filter::Project),
+            "priority" if value.eq_ignore_ascii_case("none") => Ok(Filter::Priority(None)),
+            "priority" => Priority::from_str(value, true)
+                .map(|p| Filter::Priority(Some(p)))
+                .map_err(|_| format!("'{}' is not a priority, not H/M/L or none", value)),
+            "due" if value.eq_ignore_ascii_case("none") => Ok(Filter::Due(None)),
+            "due" => date::validate_date_str(value).map(|d| Filter::Due(Some(d))),
+            "due.before" => date::validate_date_str(value).map(Filter::DueBefore),
+            "due.after" => date::validate_date_str(value).map(Filter::DueAfter),
+            _ => Err(format!("'{}' is not a filter, {}", token, FILTER_FORMS)),
+        };
+    }
+    match TagChange::from_str(token) {
+        Ok(TagChange::Add(tag)) => Ok(Filter::HasTag(tag)),
+        Ok(TagChange::Remove(tag)) => Ok(Filter::LacksTag(tag)),
+        Err(_) => Err(format!("'{}' is not a filter, {}", token, FILTER_FORMS)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::active_params::Annotation;
+
+    fn params(description: &str, tags: &[&str]) -> ActiveParams {
+        ActiveParams {
+            id: 1,
+            uuid: Uuid::nil(),
+            priority: None,
+            description: description.to_string(),
+            annotations: Vec::new(),
+            project: None,
+            tags: tags
+                .iter()
+                .map(|tag| tag.to_string())
+                .collect::<BTreeSet<String>>(),
+            due: None,
+            entry: None,
+            modified: None,
+            history: Vec::new(),
+        }
+    }
+
+    fn matches(filter: &str, params: &ActiveParams) -> bool {
+        filter.parse::<Filter>().unwrap().matches(params)
+    }
+
+    #[test]
+    fn tokenize_keeps_spaces_in_regex_and_splits_parentheses() {
+        assert_eq!(
+            tokenize("+a /foo bar/ (not +b)").unwrap(),
+            vec!["+a", "/foo bar/", "(", "not", "+b", ")"]
+        );
+        assert_eq!(tokenize(r"/a\/ b/").unwrap(), vec![r"/a\/ b/"]);
+        assert!(tokenize("/unclosed").is_err());
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        // +a or (+b and +c)
+        assert!(matches("+a or +b and +c", &params("", &["a"])));
+        assert!(!matches("+a or +b and +c", &params("", &["b"])));
+        assert!(matches("+a or +b and +c", &params("", &["b", "c"])));
+        assert!(!matches("(+a or +b) and +c", &params("", &["a"])));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_or() {
+        // (not +a) or +b
+        assert!(!matches("not +a or +b", &params("", &["a"])));
+        assert!(matches("not +a or +b", &params("", &["a", "b"])));
+        assert!(matches("not +a or +b", &params("", &[])));
+        assert!(!matches("not (+a or +b)", &params("", &["b"])));
+    }
+
+    #[test]
+    fn adjacent_terms_must_all_match() {
+        assert!(matches("+a +b", &params("", &["a", "b"])));
+        assert!(!matches("+a +b", &params("", &["a"])));
+        assert!(!matches("+a +b or +c", &params("", &["a"])));
+        assert!(matches("+a +b or +c", &params("", &["c"])));
+    }
+
+    #[test]
+    fn regex_matches_description_and_annotations() {
+        let mut task = params("deploy the api", &[]);
+        assert!(matches("/deploy the/", &task));
+        assert!(!matches("/rollback/", &task));
+        task.annotations.push(Annotation {
+            entry: None,
+            text: "rollback plan".to_string(),
+        });
+        assert!(matches("/rollback/", &task));
+        assert!("/(/".parse::<Filter>().is_err());
+    }
+
+    #[test]
+    fn lacks_tag_is_the_same_as_not_has_tag() {
+        for tags in [&[][..], &["a"][..], &["b"][..]] {
+            let task = params("", tags);
+            assert_eq!(matches("-a", &task), matches("not +a", &task));
+        }
+    }
+
+    #[test]
+    fn only_excludes_finds_filters_without_a_positive_term() {
+        let only_excludes = |s: &str| s.parse::<Filter>().unwrap().only_excludes();
+        assert!(only_excludes("-a"));
+        assert!(only_excludes("not +a"));
+        assert!(only_excludes("-a -b"));
+        assert!(only_excludes("-a or +b"));
+        assert!(!only_excludes("+b -a"));
+        assert!(!only_excludes("not +a and project:infra"));
+    }
+
+    #[test]
+    fn invalid_filters_are_errors() {
+        for filter in ["(+a", "+a)", "and +a", "+a or", "priority:X", "foo"] {
+            assert!(filter.parse::<Filter>().is_err(), "{}", filter);
+        }
+    }
+}
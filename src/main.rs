@@ -1,19 +1,31 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
 
+use active_params::{parse_project, TagChange, Update};
 use chrono::NaiveDate;
-use clap::{Parser, Subcommand, ValueEnum};
-use config::MyConfig;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use config::{ColorMode, Mode, MyConfig};
+use error::SigoError;
 use serde::{Deserialize, Serialize};
 use strum::Display;
+use task::{Selector, TaskRef};
+use urgency::SortKey;
 
 mod active_params;
 mod command;
 mod config;
 mod date;
 mod display;
+mod editor;
 mod error;
 mod file;
+mod filter;
 mod task;
+mod urgency;
 mod utils;
 
 #[derive(Parser)]
@@ -21,6 +33,14 @@ mod utils;
 struct AppArg {
     #[clap(subcommand)]
     command: Command,
+
+    /// When to color the output
+    #[arg(long, global = true, value_enum, value_name = "WHEN")]
+    color: Option<ColorMode>,
+
+    /// Output format, overriding the mode in config.ini
+    #[arg(long, global = true, value_enum, value_name = "MODE")]
+    output: Option<Mode>,
 }
 
 #[derive(Subcommand)]
@@ -30,7 +50,7 @@ enum Command {
         description: String,
 
         /// Priority(H/M/L)
-        #[arg(value_enum, short, long)]
+        #[arg(short, long, value_parser = parse_priority)]
         priority: Option<Priority>,
 
         /// Waiting
@@ -40,36 +60,89 @@ enum Command {
         /// Due date
         #[arg(short, long, value_parser = date::validate_date_str)]
         due: Option<NaiveDate>,
+
+        /// Project like infra.k8s.upgrade
+        #[arg(long, value_parser = parse_project)]
+        project: Option<String>,
+
+        /// Tags like +review
+        tags: Vec<TagChange>,
     },
 
     /// Modify sigo
     Modify {
-        id: u32,
+        /// Id, uuid prefix, list of ids like 1,3,5-8, or filter
+        id: Selector,
 
-        /// Priority(H/M/L)
-        #[arg(value_enum, short, long)]
-        priority: Option<Priority>,
+        /// Tags to add like +review, or to remove like -review
+        tags: Vec<TagChange>,
 
-        /// Due date
-        #[arg(short, long, value_parser = date::validate_date_str)]
-        due: Option<NaiveDate>,
+        /// Priority(H/M/L), or none to clear
+        #[arg(short, long, value_parser = parse_priority_update)]
+        priority: Option<Update<Priority>>,
+
+        /// Due date, or none to clear
+        #[arg(short, long, value_parser = date::validate_due_update)]
+        due: Option<Update<NaiveDate>>,
+
+        /// Description text
+        #[arg(short, long)]
+        text: Option<String>,
+
+        /// Remove all annotations
+        #[arg(long)]
+        clear_annotations: bool,
+
+        /// Project like infra.k8s.upgrade, or none to clear
+        #[arg(long, value_parser = parse_project_update)]
+        project: Option<Update<String>>,
+
+        /// Modify sigos matching a filter without confirmation
+        #[arg(short, long)]
+        yes: bool,
     },
 
+    /// Edit sigo in $EDITOR
+    Edit { id: TaskRef },
+
     /// Done sigo
-    Done { id: u32 },
+    Done {
+        /// Id, uuid prefix, list of ids like 1,3,5-8, or filter
+        id: Selector,
+
+        /// Complete sigos matching a filter without confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Delete sigo without completing it
+    Delete {
+        /// Id, uuid prefix, list of ids like 1,3,5-8, or filter
+        id: Selector,
+
+        /// Delete without confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
 
     /// Change sigo waiting
     Wait {
-        id: u32,
+        /// Id, uuid prefix, list of ids like 1,3,5-8, or filter
+        id: Selector,
 
         /// Description text
         #[arg(short, long)]
         text: Option<String>,
+
+        /// Change sigos matching a filter without confirmation
+        #[arg(short, long)]
+        yes: bool,
     },
 
     /// Change sigo ready
     Back {
-        id: u32,
+        /// Id, uuid prefix, list of ids like 1,3,5-8, or filter
+        id: Selector,
 
         /// Description text
         #[arg(short, long)]
@@ -78,18 +151,72 @@ enum Command {
 
     /// Annotate existing sigo
     Annotate {
-        id: u32,
+        /// Id, uuid prefix, list of ids like 1,3,5-8, or filter
+        id: Selector,
 
         /// Annotation text
         #[arg(short, long)]
         text: String,
+
+        /// Replace the text of the n-th annotation instead of adding one
+        #[arg(short, long, value_name = "N")]
+        edit: Option<usize>,
+    },
+
+    /// Remove the n-th annotation of sigo
+    Denotate { id: TaskRef, number: usize },
+
+    /// Reopen completed sigo
+    Reopen {
+        id: TaskRef,
+
+        /// Reopen as waiting
+        #[arg(short, long)]
+        waiting: bool,
     },
 
+    /// Show every detail of sigo
+    Info { id: TaskRef },
+
     /// List ready sigos
-    List,
+    List {
+        /// Sort keys like due,priority,id, the most urgent first by default
+        #[arg(short, long, value_enum, value_delimiter = ',')]
+        sort: Vec<SortKey>,
+
+        /// Only sigos matching the filter, like priority:H and +review
+        filter: Vec<String>,
+    },
 
     /// List waiting sigos
-    Waiting,
+    Waiting {
+        /// Sort keys like due,priority,id, the most urgent first by default
+        #[arg(short, long, value_enum, value_delimiter = ',')]
+        sort: Vec<SortKey>,
+
+        /// Only sigos matching the filter, like priority:H and +review
+        filter: Vec<String>,
+    },
+
+    /// List completed sigos
+    Completed {
+        /// Completed on or after this date
+        #[arg(short, long, value_parser = date::validate_date_str)]
+        since: Option<NaiveDate>,
+
+        /// Completed on or before this date
+        #[arg(short, long, value_parser = date::validate_date_str)]
+        until: Option<NaiveDate>,
+    },
+
+    /// List tags of ready and waiting sigos
+    Tags,
+
+    /// Count sigos per project
+    Projects,
+
+    /// Undo the latest change
+    Undo,
 }
 
 #[derive(
@@ -101,11 +228,41 @@ enum Priority {
     L,
 }
 
-fn main() {
+fn parse_priority(s: &str) -> Result<Priority, String> {
+    Priority::from_str(s, true).map_err(|_| "The priority value is invalid, not H/M/L.".to_string())
+}
+
+fn parse_priority_update(s: &str) -> Result<Update<Priority>, String> {
+    Update::parse(s, |s| {
+        Priority::from_str(s, true)
+            .map_err(|_| "The priority value is invalid, not H/M/L or none.".to_string())
+    })
+}
+
+fn parse_project_update(s: &str) -> Result<Update<String>, String> {
+    Update::parse(s, parse_project)
+}
+
+fn main() -> ExitCode {
     // load config.ini
     let xdg_dirs = xdg::BaseDirectories::with_prefix("sigotowarrior").expect("XDG is not used");
     let config_path = xdg_dirs.get_config_file("config.ini");
-    let cfg = confy::load_path::<MyConfig>(&config_path).expect("cannot load config.ini");
+    let mut cfg = match confy::load_path::<MyConfig>(&config_path) {
+        Ok(cfg) => cfg,
+        Err(err) => {
+            let err = SigoError::ParseConfigErr(config_path, config_error_message(&err));
+            return report_error(&err, wants_json(MyConfig::default().mode));
+        }
+    };
+    // a broken holidays file is reported after parsing, so --help and --version still work
+    let calendar_err =
+        match date::Calendar::load(&cfg.calendar, &xdg_dirs.get_config_file("holidays")) {
+            Ok(calendar) => {
+                date::set_calendar(calendar);
+                None
+            }
+            Err(err) => Some(err),
+        };
 
     // if task dir doesnot exist, create dir
     let sigo_path = PathBuf::from(&cfg.data);
@@ -114,11 +271,148 @@ fn main() {
     }
 
     // Parse args and Run command
-    let cli = AppArg::parse();
+    let cli = match AppArg::try_parse_from(move_tag_removals(std::env::args().collect())) {
+        Ok(cli) => cli,
+        Err(err) if err.use_stderr() && wants_json(cfg.mode) => {
+            let json = serde_json::json!({
+                "error": {"code": "usage", "message": err.to_string().trim_end()}
+            });
+            eprintln!("{}", json);
+            std::process::exit(err.exit_code());
+        }
+        Err(err) => err.exit(),
+    };
+    if let Some(err) = calendar_err {
+        return report_error(&err, wants_json(cli.output.unwrap_or(cfg.mode)));
+    }
+    if let Some(color) = cli.color {
+        cfg.color = color;
+    }
+    if let Some(mode) = cli.output {
+        cfg.mode = mode;
+    }
     match command::run(&cfg, cli) {
         Ok(output) => {
-            println!("{}", output.display(&cfg));
+            // a reader like head may close the pipe early, which is not an error
+            let mut stdout = io::stdout().lock();
+            match writeln!(stdout, "{}", output.display(&cfg)).and_then(|_| stdout.flush()) {
+                Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+                    report_error(&SigoError::StdioErr(err), matches!(cfg.mode, Mode::Json))
+                }
+                _ => ExitCode::from(output.exit_code()),
+            }
         }
-        Err(err) => eprintln!("Error: {}", err),
+        Err(err) => report_error(&err, matches!(cfg.mode, Mode::Json)),
     }
 }
+
+fn report_error(err: &SigoError, json: bool) -> ExitCode {
+    if json {
+        eprintln!("{}", err.to_json());
+    } else {
+        eprintln!("Error: {}", err);
+    }
+    ExitCode::from(err.exit_code())
+}
+
+// clap takes `-review` for an option, so the tag removals given to modify after its id
+// are moved behind a `--`, unless they are exactly one of its options like `-p`
+fn move_tag_removals(args: Vec<String>) -> Vec<String> {
+    let mut command = AppArg::command();
+    command.build();
+    let mut args = args.into_iter();
+    let mut front = args.next().into_iter().collect::<Vec<String>>();
+    let mut subcommand = None;
+    while let Some(arg) = args.next() {
+        let takes_value = option_takes_value(&command, &arg);
+        front.push(arg.clone());
+        match takes_value {
+            Some(true) => front.extend(args.next()),
+            Some(false) => {}
+            None if arg.starts_with('-') => {}
+            None => {
+                subcommand = command.find_subcommand(&arg);
+                break;
+            }
+        }
+    }
+    let Some(subcommand) = subcommand.filter(|s| s.get_name() == "modify") else {
+        front.extend(args);
+        return front;
+    };
+
+    let mut removals = Vec::new();
+    let mut has_id = false;
+    let mut has_separator = false;
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            front.push(arg);
+            front.extend(args.by_ref());
+            has_separator = true;
+            break;
+        }
+        match option_takes_value(subcommand, &arg) {
+            Some(takes_value) => {
+                front.push(arg);
+                if takes_value {
+                    front.extend(args.next());
+                }
+            }
+            None if has_id && arg.len() > 1 && arg.starts_with('-') && !arg.starts_with("--") => {
+                removals.push(arg)
+            }
+            None => {
+                has_id |= !arg.starts_with('-');
+                front.push(arg);
+            }
+        }
+    }
+    if !removals.is_empty() && !has_separator {
+        front.push("--".to_string());
+    }
+    front.extend(removals);
+    front
+}
+
+// None when the argument is not an option of the command
+fn option_takes_value(command: &clap::Command, arg: &str) -> Option<bool> {
+    if let Some(long) = arg.strip_prefix("--") {
+        let (name, has_value) = match long.split_once('=') {
+            Some((name, _)) => (name, true),
+            None => (long, false),
+        };
+        return command
+            .get_arguments()
+            .find(|a| a.get_long() == Some(name))
+            .map(|a| !has_value && a.get_action().takes_values());
+    }
+    let mut chars = arg.strip_prefix('-')?.chars();
+    let (Some(short), None) = (chars.next(), chars.next()) else {
+        return None;
+    };
+    command
+        .get_arguments()
+        .find(|a| a.get_short() == Some(short))
+        .map(|a| a.get_action().takes_values())
+}
+
+// the TOML error spans several lines quoting the file, keep only its message
+fn config_error_message(err: &confy::ConfyError) -> String {
+    match err {
+        confy::ConfyError::BadTomlData(err) => err.message().trim().to_string(),
+        err => match std::error::Error::source(err) {
+            Some(source) => format!("{}: {}", err, source),
+            None => err.to_string(),
+        },
+    }
+}
+
+// when the arguments don't parse, --output json can only be found in the raw arguments
+fn wants_json(mode: Mode) -> bool {
+    let args = std::env::args().collect::<Vec<String>>();
+    matches!(mode, Mode::Json)
+        || args.iter().any(|arg| arg == "--output=json")
+        || args
+            .windows(2)
+            .any(|pair| pair[0] == "--output" && pair[1] == "json")
+}
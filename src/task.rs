@@ -1,22 +1,123 @@
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
-use chrono::NaiveDate;
+use chrono::{DateTime, Local, NaiveDate};
 use serde::{Deserialize, Serialize};
 use tabled::Tabled;
+use uuid::Uuid;
 
-use crate::active_params::ActiveParams;
-use crate::config::MyConfig;
+use crate::active_params::{ActiveParams, State};
 use crate::error::SigoError;
-use crate::file::{add_task, delete_by_id, get_by_id, read_tasks, ActiveFilable};
-use crate::Priority;
+use crate::file::{
+    add_task, delete_by_id, get_by_id, read_tasks, write_tasks, ActiveFilable, Transaction,
+};
+use crate::filter::Filter;
+use crate::{utils, Priority};
 
-#[derive(Tabled, Serialize, Deserialize, Debug)]
+#[derive(Tabled, Serialize, Deserialize, Debug, Clone)]
 pub enum Task {
     Ready(ReadyTask),
     Waiting(WaitingTask),
     Completed(CompletedTask),
 }
 
+const UUID_PREFIX_MIN_DIGITS: usize = 8;
+
+/// How a sigo is pointed at on the command line:
+/// a short id like `3`, or a unique prefix of its uuid like `1f0c9a`.
+/// A prefix made only of digits needs at least `UUID_PREFIX_MIN_DIGITS` of them.
+#[derive(Clone, Debug)]
+pub enum TaskRef {
+    Id(u32),
+    Uuid(String),
+}
+
+impl FromStr for TaskRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(id) = parse_id(s) {
+            return Ok(TaskRef::Id(id));
+        }
+        let prefix = s.to_lowercase();
+        if !prefix.is_empty() && prefix.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+            Ok(TaskRef::Uuid(prefix))
+        } else {
+            Err("The value is neither a sigo id nor a uuid prefix.".to_string())
+        }
+    }
+}
+
+/// Which sigos a command applies to: one sigo by id or uuid prefix, a list of ids and
+/// ranges like `1,3,5-8`, or every ready and waiting sigo matching a filter like
+/// `"+review and project:infra"`.
+#[derive(Clone, Debug)]
+pub enum Selector {
+    Task(TaskRef),
+    Ids(Vec<RangeInclusive<u32>>),
+    Filter(Filter),
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(',') {
+            return s
+                .split(',')
+                .map(|part| {
+                    parse_id_range(part.trim()).ok_or(format!(
+                        "'{}' is neither a sigo id nor a range like 5-8.",
+                        part
+                    ))
+                })
+                .collect::<Result<Vec<RangeInclusive<u32>>, String>>()
+                .map(Selector::Ids);
+        }
+        if let Some(range) = parse_id_range(s) {
+            if range.start() == range.end() {
+                return Ok(Selector::Task(TaskRef::Id(*range.start())));
+            }
+            return Ok(Selector::Ids(vec![range]));
+        }
+        if let Ok(task_ref) = TaskRef::from_str(s) {
+            return Ok(Selector::Task(task_ref));
+        }
+        match Filter::from_str(s) {
+            Ok(Filter::All) => Err("The filter selecting sigos cannot be empty.".to_string()),
+            Ok(filter) if filter.only_excludes() => Err(
+                "The filter selecting sigos needs a term like +tag or project:<name>, \
+                 not only -tag or not."
+                    .to_string(),
+            ),
+            Ok(filter) => Ok(Selector::Filter(filter)),
+            Err(e) => Err(format!(
+                "The value is neither a sigo id, a uuid prefix nor a filter: {}.",
+                e
+            )),
+        }
+    }
+}
+
+// ids are short, so a uuid prefix of digits only is told apart by its length
+fn parse_id(s: &str) -> Option<u32> {
+    if s.is_empty() || s.len() >= UUID_PREFIX_MIN_DIGITS || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse::<u32>().ok()
+}
+
+fn parse_id_range(s: &str) -> Option<RangeInclusive<u32>> {
+    match s.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (parse_id(start)?, parse_id(end)?);
+            (start <= end).then_some(start..=end)
+        }
+        None => parse_id(s).map(|id| id..=id),
+    }
+}
+
 #[derive(Tabled, Serialize, Deserialize, Debug, Clone, Ord, PartialEq, PartialOrd, Eq)]
 pub struct ReadyTask {
     #[tabled(inline)]
@@ -30,24 +131,232 @@ pub struct WaitingTask {
 }
 
 #[derive(Tabled, Serialize, Deserialize, Debug, Clone)]
+#[serde(from = "CompletedTaskRecord")]
 pub struct CompletedTask {
-    pub summary: String,
+    #[tabled(inline)]
+    pub active_params: ActiveParams,
+    #[tabled(rename = "completed", display_with = "utils::display_option_datetime")]
+    pub completed_at: Option<DateTime<Local>>,
+}
+
+/// Tombstone of a sigo removed by `sigo delete`, kept for auditing and recovery.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeletedTask {
+    pub active_params: ActiveParams,
+    pub deleted_at: DateTime<Local>,
+}
+
+impl CompletedTask {
+    // sigos completed before the completion time was recorded match only when no range is given
+    pub fn is_completed_between(&self, since: Option<NaiveDate>, until: Option<NaiveDate>) -> bool {
+        if since.is_none() && until.is_none() {
+            return true;
+        }
+        match self.completed_at {
+            Some(completed_at) => {
+                let date = completed_at.date_naive();
+                since.is_none_or(|since| since <= date) && until.is_none_or(|until| date <= until)
+            }
+            None => false,
+        }
+    }
+
+    // ids of completed sigos can be reused, so the latest completed one wins
+    // ids are reused once a sigo is completed, so several completed sigos may share one
+    pub fn get_by_id(tx: &Transaction, id: u32) -> Result<Self, SigoError> {
+        let mut matches = read_tasks::<CompletedTask>(tx)?
+            .into_iter()
+            .filter(|t| t.active_params.id == id)
+            .collect::<Vec<CompletedTask>>();
+        match matches.len() {
+            0 => Err(SigoError::TaskNotFound(id)),
+            1 => Ok(matches.remove(0)),
+            _ => Err(SigoError::AmbiguousId(
+                id,
+                matches
+                    .iter()
+                    .map(|t| {
+                        t.active_params.uuid.simple().to_string()[..UUID_PREFIX_MIN_DIGITS]
+                            .to_string()
+                    })
+                    .collect(),
+            )),
+        }
+    }
+
+    pub fn get(tx: &Transaction, task_ref: &TaskRef) -> Result<Self, SigoError> {
+        match task_ref {
+            TaskRef::Id(id) => CompletedTask::get_by_id(tx, *id),
+            TaskRef::Uuid(prefix) => match Task::get_by_uuid_prefix(tx, prefix)? {
+                Task::Completed(task) => Ok(task),
+                Task::Ready(task) => Err(SigoError::TaskNotCompleted(task.active_params.id)),
+                Task::Waiting(task) => Err(SigoError::TaskNotCompleted(task.active_params.id)),
+            },
+        }
+    }
+
+    pub fn reopen(self, tx: &mut Transaction) -> Result<ReadyTask, SigoError> {
+        let mut completed_tasks = read_tasks::<CompletedTask>(tx)?;
+        let index = completed_tasks
+            .iter()
+            .rposition(|t| t.active_params.uuid == self.active_params.uuid)
+            .ok_or(SigoError::TaskNotFound(self.active_params.id))?;
+        completed_tasks.remove(index);
+        write_tasks::<CompletedTask>(tx, completed_tasks)?;
+        let id = Task::issue_task_id(tx)?;
+        add_task::<ReadyTask>(tx, ReadyTask::from_completed(self, id))
+    }
+}
+
+// completed_tasks written before the full params were kept only hold a flattened summary
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CompletedTaskRecord {
+    Full {
+        active_params: ActiveParams,
+        completed_at: Option<DateTime<Local>>,
+    },
+    Summary {
+        summary: String,
+    },
+}
+
+impl From<CompletedTaskRecord> for CompletedTask {
+    fn from(record: CompletedTaskRecord) -> Self {
+        match record {
+            CompletedTaskRecord::Full {
+                active_params,
+                completed_at,
+            } => Self {
+                active_params,
+                completed_at,
+            },
+            CompletedTaskRecord::Summary { summary } => Self {
+                active_params: ActiveParams {
+                    id: 0,
+                    // set from the position in the file by Filable::at_position
+                    uuid: Uuid::nil(),
+                    description: summary,
+                    annotations: Vec::new(),
+                    tags: BTreeSet::new(),
+                    project: None,
+                    priority: None,
+                    due: None,
+                    entry: None,
+                    modified: None,
+                    history: Vec::new(),
+                },
+                completed_at: None,
+            },
+        }
+    }
 }
 
 impl Task {
-    pub fn get_by_id(cfg: &MyConfig, id: u32) -> Result<Task, SigoError> {
-        if let Ok(task) = get_by_id::<ReadyTask>(cfg, id) {
-            return Ok(Task::Ready(task));
+    pub fn active_params(&self) -> &ActiveParams {
+        match self {
+            Task::Ready(task) => &task.active_params,
+            Task::Waiting(task) => &task.active_params,
+            Task::Completed(task) => &task.active_params,
         }
-        if let Ok(task) = get_by_id::<WaitingTask>(cfg, id) {
-            return Ok(Task::Waiting(task));
+    }
+
+    pub fn state(&self) -> State {
+        match self {
+            Task::Ready(_) => State::Ready,
+            Task::Waiting(_) => State::Waiting,
+            Task::Completed(_) => State::Completed,
         }
-        Err(SigoError::TaskNotFound(id))
     }
 
-    fn issue_task_id(cfg: &MyConfig) -> Result<u32, SigoError> {
-        let ready_tasks = read_tasks::<ReadyTask>(cfg)?;
-        let waiting_tasks = read_tasks::<WaitingTask>(cfg)?;
+    // only a sigo missing from a store moves on to the next one, other errors are returned
+    pub fn get_by_id(tx: &Transaction, id: u32) -> Result<Task, SigoError> {
+        match get_by_id::<ReadyTask>(tx, id) {
+            Err(SigoError::TaskNotFound(_)) => {}
+            result => return result.map(Task::Ready),
+        }
+        match get_by_id::<WaitingTask>(tx, id) {
+            Err(SigoError::TaskNotFound(_)) => {}
+            result => return result.map(Task::Waiting),
+        }
+        CompletedTask::get_by_id(tx, id).map(Task::Completed)
+    }
+
+    pub fn get(tx: &Transaction, task_ref: &TaskRef) -> Result<Task, SigoError> {
+        match task_ref {
+            TaskRef::Id(id) => Task::get_by_id(tx, *id),
+            TaskRef::Uuid(prefix) => Task::get_by_uuid_prefix(tx, prefix),
+        }
+    }
+
+    pub fn select(tx: &Transaction, filter: &Filter) -> Result<Vec<Task>, SigoError> {
+        let mut tasks = Vec::new();
+        for task in read_tasks::<ReadyTask>(tx)? {
+            if filter.matches(&task.active_params) {
+                tasks.push(Task::Ready(task));
+            }
+        }
+        for task in read_tasks::<WaitingTask>(tx)? {
+            if filter.matches(&task.active_params) {
+                tasks.push(Task::Waiting(task));
+            }
+        }
+        Ok(tasks)
+    }
+
+    // a single id must exist, a range takes the ready and waiting sigos within it
+    pub fn get_by_ids(
+        tx: &Transaction,
+        ranges: &[RangeInclusive<u32>],
+    ) -> Result<Vec<Result<Task, SigoError>>, SigoError> {
+        let mut active_tasks = Task::select(tx, &Filter::All)?;
+        active_tasks.sort_by_key(|t| t.active_params().id);
+        let mut seen = HashSet::new();
+        let mut tasks = Vec::new();
+        for range in ranges {
+            if range.start() == range.end() {
+                if seen.insert(*range.start()) {
+                    tasks.push(Task::get_by_id(tx, *range.start()));
+                }
+                continue;
+            }
+            for task in active_tasks.iter() {
+                let id = task.active_params().id;
+                if range.contains(&id) && seen.insert(id) {
+                    tasks.push(Ok(task.clone()));
+                }
+            }
+        }
+        Ok(tasks)
+    }
+
+    fn get_by_uuid_prefix(tx: &Transaction, prefix: &str) -> Result<Task, SigoError> {
+        let mut tasks = Vec::new();
+        for task in read_tasks::<ReadyTask>(tx)? {
+            if task.active_params.has_uuid_prefix(prefix) {
+                tasks.push(Task::Ready(task));
+            }
+        }
+        for task in read_tasks::<WaitingTask>(tx)? {
+            if task.active_params.has_uuid_prefix(prefix) {
+                tasks.push(Task::Waiting(task));
+            }
+        }
+        for task in read_tasks::<CompletedTask>(tx)? {
+            if task.active_params.has_uuid_prefix(prefix) {
+                tasks.push(Task::Completed(task));
+            }
+        }
+        match tasks.len() {
+            0 => Err(SigoError::UuidNotFound(prefix.to_string())),
+            1 => Ok(tasks.remove(0)),
+            n => Err(SigoError::AmbiguousUuid(prefix.to_string(), n)),
+        }
+    }
+
+    fn issue_task_id(tx: &Transaction) -> Result<u32, SigoError> {
+        let ready_tasks = read_tasks::<ReadyTask>(tx)?;
+        let waiting_tasks = read_tasks::<WaitingTask>(tx)?;
         let mut using_ids = HashSet::new();
         for task in ready_tasks.iter() {
             using_ids.insert(task.active_params.id);
@@ -62,50 +371,162 @@ impl Task {
 
 impl ReadyTask {
     pub fn new(
-        cfg: &MyConfig,
+        tx: &Transaction,
         description: &str,
         priority: Option<Priority>,
         due: Option<NaiveDate>,
+        tags: BTreeSet<String>,
+        project: Option<String>,
     ) -> Result<Self, SigoError> {
-        let id = Task::issue_task_id(cfg)?;
+        let id = Task::issue_task_id(tx)?;
+        let now = Local::now();
         Ok(Self {
             active_params: ActiveParams {
                 id,
-                description: vec![description.to_owned()],
+                uuid: Uuid::new_v4(),
+                description: description.to_owned(),
+                annotations: Vec::new(),
+                tags,
+                project,
                 priority,
                 due,
+                entry: Some(now),
+                modified: Some(now),
+                history: Vec::new(),
             },
         })
     }
 
     fn from_waiting(waiting_task: WaitingTask) -> Self {
         ReadyTask {
-            active_params: waiting_task.active_params,
+            active_params: waiting_task.active_params.change_state(State::Ready),
+        }
+    }
+
+    fn from_completed(completed_task: CompletedTask, id: u32) -> Self {
+        ReadyTask {
+            active_params: ActiveParams {
+                id,
+                ..completed_task.active_params.change_state(State::Ready)
+            },
         }
     }
 
-    pub fn wait(self, cfg: &MyConfig, text: &Option<String>) -> Result<WaitingTask, SigoError> {
-        delete_by_id::<ReadyTask>(cfg, self.active_params.id)?;
-        let task = add_task::<WaitingTask>(cfg, WaitingTask::from_ready(self))?;
-        if let Some(text) = text {
-            task.annotate(cfg, text)?;
+    pub fn wait(
+        self,
+        tx: &mut Transaction,
+        text: &Option<String>,
+    ) -> Result<WaitingTask, SigoError> {
+        delete_by_id::<ReadyTask>(tx, self.active_params.id)?;
+        let task = add_task::<WaitingTask>(tx, WaitingTask::from_ready(self))?;
+        match text {
+            Some(text) => task.annotate(tx, text),
+            None => Ok(task),
         }
-        Ok(task)
     }
 }
 impl WaitingTask {
     fn from_ready(ready_task: ReadyTask) -> Self {
         Self {
-            active_params: ready_task.active_params,
+            active_params: ready_task.active_params.change_state(State::Waiting),
+        }
+    }
+
+    pub fn back(self, tx: &mut Transaction, text: &Option<String>) -> Result<ReadyTask, SigoError> {
+        delete_by_id::<WaitingTask>(tx, self.active_params.id)?;
+        let task = add_task::<ReadyTask>(tx, ReadyTask::from_waiting(self))?;
+        match text {
+            Some(text) => task.annotate(tx, text),
+            None => Ok(task),
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
 
-    pub fn back(self, cfg: &MyConfig, text: &Option<String>) -> Result<ReadyTask, SigoError> {
-        delete_by_id::<WaitingTask>(cfg, self.active_params.id)?;
-        let task = add_task::<ReadyTask>(cfg, ReadyTask::from_waiting(self))?;
-        if let Some(text) = text {
-            task.annotate(cfg, text)?;
+    #[test]
+    fn short_digits_are_an_id_and_longer_ones_a_uuid_prefix() {
+        assert!(matches!(TaskRef::from_str("7"), Ok(TaskRef::Id(7))));
+        assert!(matches!(
+            TaskRef::from_str("12345678"),
+            Ok(TaskRef::Uuid(prefix)) if prefix == "12345678"
+        ));
+        assert!(matches!(
+            TaskRef::from_str("1F0C"),
+            Ok(TaskRef::Uuid(prefix)) if prefix == "1f0c"
+        ));
+        assert!(TaskRef::from_str("+review").is_err());
+    }
+
+    #[test]
+    fn selector_reads_ids_ranges_and_uuid_prefixes() {
+        assert!(matches!(
+            Selector::from_str("7"),
+            Ok(Selector::Task(TaskRef::Id(7)))
+        ));
+        assert!(matches!(
+            Selector::from_str("12345678"),
+            Ok(Selector::Task(TaskRef::Uuid(_)))
+        ));
+        assert!(matches!(
+            Selector::from_str("3-3"),
+            Ok(Selector::Task(TaskRef::Id(3)))
+        ));
+        assert!(matches!(
+            Selector::from_str("1f0c"),
+            Ok(Selector::Task(TaskRef::Uuid(_)))
+        ));
+        match Selector::from_str("1,3,5-8") {
+            Ok(Selector::Ids(ranges)) => assert_eq!(ranges, vec![1..=1, 3..=3, 5..=8]),
+            _ => panic!("expected a list of ids"),
         }
-        Ok(task)
+        assert!(Selector::from_str("1,x").is_err());
+    }
+
+    #[test]
+    fn selector_filter_needs_a_positive_term() {
+        assert!(matches!(
+            Selector::from_str("+review"),
+            Ok(Selector::Filter(_))
+        ));
+        assert!(matches!(
+            Selector::from_str("+review and -blocked"),
+            Ok(Selector::Filter(_))
+        ));
+        assert!(Selector::from_str("-review").is_err());
+        assert!(Selector::from_str("not +review").is_err());
+        assert!(Selector::from_str("+review or -blocked").is_err());
+    }
+
+    #[test]
+    fn completed_summary_is_read_as_a_description() {
+        let task = serde_json::from_value::<CompletedTask>(json!({"summary": "write the report"}))
+            .unwrap();
+        assert_eq!(task.active_params.description, "write the report");
+        assert!(task.active_params.annotations.is_empty());
+        assert!(task.active_params.uuid.is_nil());
+        assert_eq!(task.completed_at, None);
+    }
+
+    #[test]
+    fn completed_record_keeps_its_params_and_completion_time() {
+        let task = serde_json::from_value::<CompletedTask>(json!({
+            "active_params": {
+                "id": 3,
+                "uuid": "67e55044-10b1-426f-9247-bb680e5fe0c8",
+                "priority": null,
+                "description": "write the report",
+                "due": null
+            },
+            "completed_at": "2024-06-01T09:00:00+09:00"
+        }))
+        .unwrap();
+        assert_eq!(task.active_params.id, 3);
+        assert!(!task.active_params.uuid.is_nil());
+        assert!(task.completed_at.is_some());
     }
 }
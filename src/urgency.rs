@@ -0,0 +1,119 @@
+use std::{borrow::Cow, cmp::Ordering};
+
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use tabled::Tabled;
+
+use crate::{
+    active_params::{ActiveParams, State},
+    config::UrgencyConfig,
+    date, Priority,
+};
+
+/// Keys of `sigo list --sort`, urgency sorts the most urgent first,
+/// the others in ascending order with missing values last.
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum SortKey {
+    Urgency,
+    Due,
+    Priority,
+    Id,
+    Entry,
+    Project,
+    Description,
+}
+
+// each factor is scaled to 0.0..=1.0 and multiplied by its weight
+pub fn urgency(params: &ActiveParams, cfg: &UrgencyConfig, now: DateTime<Local>) -> f64 {
+    let mut urgency = match params.priority {
+        Some(Priority::H) => cfg.priority_high,
+        Some(Priority::M) => cfg.priority_medium,
+        Some(Priority::L) => cfg.priority_low,
+        None => 0.0,
+    };
+    if let Some(due) = params.due {
+        let today = now.date_naive();
+        let days_left = date::calendar().working_days_until(today, due);
+        urgency += cfg.due * (1.0 - ratio(days_left, cfg.due_days)).max(0.0);
+        if due < today {
+            urgency += cfg.overdue;
+        }
+    }
+    if let Some(entry) = params.entry {
+        urgency += cfg.age * ratio((now - entry).num_days(), cfg.age_days);
+    }
+    if let Some(change) = params.history.last() {
+        if change.state == State::Waiting {
+            urgency += cfg.waiting * ratio((now - change.at).num_days(), cfg.waiting_days);
+        }
+    }
+    for tag in params.tags.iter() {
+        urgency += cfg.tags.get(tag).copied().unwrap_or_default();
+    }
+    urgency
+}
+
+fn ratio(days: i64, max_days: i64) -> f64 {
+    if max_days <= 0 {
+        return 1.0;
+    }
+    (days as f64 / max_days as f64).clamp(0.0, 1.0)
+}
+
+/// A sigo with its urgency, listed with an extra urg column.
+pub struct Urgent<T> {
+    pub task: T,
+    pub urgency: f64,
+}
+
+impl<T: Tabled> Tabled for Urgent<T> {
+    const LENGTH: usize = T::LENGTH + 1;
+
+    fn fields(&self) -> Vec<Cow<'_, str>> {
+        let mut fields = self.task.fields();
+        fields.push(Cow::Owned(format!("{:.1}", self.urgency)));
+        fields
+    }
+
+    fn headers() -> Vec<Cow<'static, str>> {
+        let mut headers = T::headers();
+        headers.push(Cow::Borrowed("urg"));
+        headers
+    }
+}
+
+// later keys break ties of earlier ones, and the id keeps the order stable
+pub fn sort<T, F>(tasks: &mut [Urgent<T>], keys: &[SortKey], params: F)
+where
+    F: Fn(&T) -> &ActiveParams,
+{
+    let keys = if keys.is_empty() {
+        &[SortKey::Urgency][..]
+    } else {
+        keys
+    };
+    tasks.sort_by(|a, b| {
+        let (pa, pb) = (params(&a.task), params(&b.task));
+        keys.iter()
+            .map(|key| match key {
+                SortKey::Urgency => b.urgency.total_cmp(&a.urgency),
+                SortKey::Due => cmp_option(&pa.due, &pb.due),
+                SortKey::Priority => pa.cmp(pb),
+                SortKey::Id => pa.id.cmp(&pb.id),
+                SortKey::Entry => cmp_option(&pa.entry, &pb.entry),
+                SortKey::Project => cmp_option(&pa.project, &pb.project),
+                SortKey::Description => pa.description.cmp(&pb.description),
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| pa.id.cmp(&pb.id))
+    });
+}
+
+fn cmp_option<T: Ord>(a: &Option<T>, b: &Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
@@ -1,6 +1,11 @@
-use std::{collections::HashMap, fs, io::Write, path::PathBuf};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    io::{self, IsTerminal, Write},
+    path::PathBuf,
+};
 
-use chrono::NaiveDate;
+use chrono::{DateTime, Local, NaiveDate};
 use tabled::{
     grid::{
         config::{ColoredConfig, Entity, HorizontalLine},
@@ -15,37 +20,77 @@ use tabled::{
     },
     Table, Tabled,
 };
-use terminal_size::{terminal_size, Height as TerminalHeight, Width as TerminalWidth};
+use terminal_size::{terminal_size, Width as TerminalWidth};
+
+use crate::{
+    active_params::ActiveParams,
+    config::{ColorConfig, ColorMode, MyConfig, Style},
+    date,
+    error::SigoError,
+    Priority,
+};
 
-use crate::{error::SigoError, Priority};
+// narrow terminals still get a readable description column
+const MIN_DESCRIPTION_WIDTH: usize = 20;
 
-// TODO priority high is bold, low is toumei
 // this could be macro, i donot know...
-pub fn tasks_to_string<I, T>(tasks: I) -> String
+pub fn tasks_to_string<I, T>(tasks: I, cfg: &MyConfig) -> String
 where
     I: IntoIterator<Item = T>,
     T: Tabled,
 {
-    let (width, _height) = get_terminal_size();
     let mut style = Theme::default();
     style.set_lines_horizontal(HashMap::from_iter([(
         1,
         HorizontalLine::full('-', ' ', ' ', ' '),
     )]));
     style.set_border_intersection_top(' ');
-    Table::new(tasks)
+    let headers = T::headers();
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    // completed sigos are not late, whatever their due date was
+    let due_column = column("due").filter(|_| column("completed").is_none());
+    let colorization = RowColorization {
+        priority_column: column("P"),
+        due_column,
+        today: Local::now().date_naive(),
+        colors: cfg.colors.clone(),
+    };
+    let mut table = Table::new(tasks);
+    table
         .modify(Rows::new(..), Padding::new(0, 0, 0, 0))
-        .with(Modify::new(Columns::single(0)).with(Alignment::right()))
-        .with(Modify::new(Columns::single(2)).with(Width::wrap(width / 2)))
-        .with(Modify::new(Rows::new(1..)).with(PriorityColorization))
-        .with(style)
-        .to_string()
+        .with(Modify::new(Columns::single(0)).with(Alignment::right()));
+    if let (Some(description_column), Some(width)) = (column("description"), terminal_width()) {
+        let width = (width / 2).max(MIN_DESCRIPTION_WIDTH);
+        table.with(Modify::new(Columns::single(description_column)).with(Width::wrap(width)));
+    }
+    if use_color(cfg.color) {
+        table.with(Modify::new(Rows::new(1..)).with(colorization));
+    }
+    table.with(style).to_string()
+}
+
+// always and never win over NO_COLOR, auto colors only a terminal
+fn use_color(mode: ColorMode) -> bool {
+    match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => {
+            io::stdout().is_terminal()
+                && std::env::var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty())
+        }
+    }
 }
 
+// a row gets the style of its priority and of its due date together
 #[derive(Clone)]
-struct PriorityColorization;
+struct RowColorization {
+    priority_column: Option<usize>,
+    due_column: Option<usize>,
+    today: NaiveDate,
+    colors: ColorConfig,
+}
 
-impl CellOption<VecRecords<CellInfo<String>>, ColoredConfig> for PriorityColorization {
+impl CellOption<VecRecords<CellInfo<String>>, ColoredConfig> for RowColorization {
     fn change(
         self,
         records: &mut VecRecords<CellInfo<String>>,
@@ -55,19 +100,46 @@ impl CellOption<VecRecords<CellInfo<String>>, ColoredConfig> for PriorityColoriz
         let (count_rows, count_cols) = (records.count_rows(), records.count_columns());
 
         for (row, col) in entity.iter(count_rows, count_cols) {
-            let priority = records[row][1].as_ref();
-            let color = priority_color(priority);
-            cfg.set_color(Entity::Cell(row, col), color.into());
+            let priority = self
+                .priority_column
+                .map(|column| priority_style(&self.colors, records[row][column].as_ref()))
+                .unwrap_or_default();
+            let due = self
+                .due_column
+                .map(|column| due_style(&self.colors, records[row][column].as_ref(), self.today))
+                .unwrap_or_default();
+            let style = priority.compose(&due);
+            cfg.set_color(
+                Entity::Cell(row, col),
+                Color::new(style.prefix, style.suffix).into(),
+            );
         }
     }
 }
 
-fn priority_color(priority: &str) -> Color {
+fn priority_style(colors: &ColorConfig, priority: &str) -> Style {
     match priority {
-        "H" => Color::BOLD,
-        "M" => Color::default(),
-        "L" => Color::new("\u{1b}[2m", "\u{1b}[22m"),
-        _ => Color::default(),
+        "H" => colors.priority_high.clone(),
+        "M" => colors.priority_medium.clone(),
+        "L" => colors.priority_low.clone(),
+        _ => Style::default(),
+    }
+}
+
+fn due_style(colors: &ColorConfig, due: &str, today: NaiveDate) -> Style {
+    let Ok(due) = due.parse::<NaiveDate>() else {
+        return Style::default();
+    };
+    // a sigo due on a weekend is due today on the last working day before it
+    let days_left = date::calendar().working_days_until(today, due);
+    if due < today {
+        colors.overdue.clone()
+    } else if days_left == 0 {
+        colors.due_today.clone()
+    } else if days_left <= colors.due_soon_days {
+        colors.due_soon.clone()
+    } else {
+        Style::default()
     }
 }
 
@@ -81,8 +153,35 @@ pub fn create_file_if_not_exist(path: &PathBuf) -> Result<(), SigoError> {
     Ok(())
 }
 
-pub fn display_vec_string(v: &[String]) -> String {
-    v.join("\n* ")
+// ask a yes/no question on stdin, anything but y/yes means no.
+// the question goes to stderr to keep stdout for the result, like JSON
+pub fn confirm(question: &str) -> Result<bool, SigoError> {
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush().map_err(SigoError::StdioErr)?;
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(SigoError::StdioErr)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+pub fn display_description(params: &ActiveParams) -> String {
+    let mut lines = vec![params.description.clone()];
+    for (index, annotation) in params.annotations.iter().enumerate() {
+        lines.push(format!("{}. {}", index + 1, annotation.text));
+    }
+    lines.join("\n")
+}
+
+pub fn display_tags(tags: &BTreeSet<String>) -> String {
+    tags.iter()
+        .map(|tag| format!("+{}", tag))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+pub fn display_option_string(o: &Option<String>) -> String {
+    o.clone().unwrap_or_default()
 }
 
 pub fn display_option_priority(o: &Option<Priority>) -> String {
@@ -99,9 +198,38 @@ pub fn display_option_date(o: &Option<NaiveDate>) -> String {
     }
 }
 
-fn get_terminal_size() -> (usize, usize) {
-    let (TerminalWidth(width), TerminalHeight(height)) =
-        terminal_size().expect("failed to obtain a terminal size");
+pub fn display_option_datetime(o: &Option<DateTime<Local>>) -> String {
+    match o {
+        Some(dt) => dt.format("%Y-%m-%d %H:%M").to_string(),
+        None => "".to_string(),
+    }
+}
 
-    (width as usize, height as usize)
+pub fn display_option_age(o: &Option<DateTime<Local>>) -> String {
+    match o {
+        Some(dt) => {
+            let age = Local::now() - *dt;
+            if age.num_days() > 0 {
+                format!("{}d", age.num_days())
+            } else if age.num_hours() > 0 {
+                format!("{}h", age.num_hours())
+            } else if age.num_minutes() > 0 {
+                format!("{}min", age.num_minutes())
+            } else {
+                format!("{}s", age.num_seconds())
+            }
+        }
+        None => "".to_string(),
+    }
+}
+
+// without a terminal, as in pipes and cron jobs, $COLUMNS or no limit
+fn terminal_width() -> Option<usize> {
+    match terminal_size() {
+        Some((TerminalWidth(width), _)) => Some(width as usize),
+        None => std::env::var("COLUMNS")
+            .ok()
+            .and_then(|columns| columns.parse::<usize>().ok())
+            .filter(|columns| *columns > 0),
+    }
 }